@@ -0,0 +1,149 @@
+//! Blocking API for callers without an async runtime, available through the
+//! `blocking` feature.
+//!
+//! The Expert Bundle is downloaded through `reqwest::blocking`, while the
+//! Tor process is driven by a Tokio runtime owned by [`Tor`]. Either way,
+//! calls panic when made from within another Tokio runtime.
+
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::time::Duration;
+
+use anyhow::Context;
+use tokio::runtime::{Builder, Runtime};
+
+use crate::{CheckResult, DownloadOptions, Result, TorError, VersionSelection};
+
+/// Blocking counterpart of [`crate::Tor`]. Its runtime keeps a worker thread
+/// reading Tor's output in the background between calls.
+///
+/// Methods not requiring `&mut self` or an async runtime are available
+/// through [`Deref`].
+pub struct Tor {
+    tor: crate::Tor,
+    runtime: Runtime,
+}
+
+impl Tor {
+    /// Wraps a configured [`crate::Tor`] instance.
+    pub fn new(tor: crate::Tor) -> Result<Self> {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .context("Failed to build Tokio runtime.")
+            .map_err(TorError::Other)?;
+
+        Ok(Self { tor, runtime })
+    }
+
+    /// See [`crate::Tor::setup_with_version`].
+    pub fn setup_with_version(version_selection: VersionSelection) -> Result<Self> {
+        Self::setup_with_options(
+            DownloadOptions::default().with_version_selection(version_selection),
+        )
+    }
+
+    /// See [`crate::Tor::setup_with_options`]. The Expert Bundle is
+    /// downloaded through [`DownloadOptions::build_blocking`] and
+    /// [`Downloader::download_blocking`].
+    ///
+    /// [`Downloader::download_blocking`]: crate::Downloader::download_blocking
+    pub fn setup_with_options(options: DownloadOptions) -> Result<Self> {
+        let downloader = options.build_blocking()?;
+
+        crate::Tor::ensure_host_target(&downloader)?;
+        downloader.download_blocking()?;
+
+        let mut tor = Self::new(crate::Tor::from_downloader(&downloader)?)?;
+
+        tor.runtime.block_on(tor.tor.detect_runtime_version());
+        Ok(tor)
+    }
+
+    /// See [`crate::Tor::setup`].
+    pub fn setup() -> Result<Self> {
+        Self::setup_with_version(VersionSelection::default())
+    }
+
+    /// See [`crate::Tor::run`].
+    pub fn run(&mut self) -> Result<u32> {
+        self.runtime.block_on(self.tor.run())
+    }
+
+    /// See [`crate::Tor::force_run`].
+    pub fn force_run(&mut self) -> Result<u32> {
+        self.runtime.block_on(self.tor.force_run())
+    }
+
+    /// See [`crate::Tor::run_with_timeout`].
+    pub fn run_with_timeout(&mut self, timeout: Duration) -> Result<u32> {
+        self.runtime.block_on(self.tor.run_with_timeout(timeout))
+    }
+
+    /// See [`crate::Tor::run_with_config`].
+    pub fn run_with_config(&mut self, torrc: PathBuf) -> Result<u32> {
+        self.runtime.block_on(self.tor.run_with_config(torrc))
+    }
+
+    /// See [`crate::Tor::spawn`].
+    pub fn spawn(&mut self) -> Result<u32> {
+        let _guard = self.runtime.enter();
+
+        self.tor.spawn()
+    }
+
+    /// See [`crate::Tor::wait_bootstrapped`].
+    pub fn wait_bootstrapped(&mut self) -> Result<()> {
+        self.runtime.block_on(self.tor.wait_bootstrapped())
+    }
+
+    /// See [`crate::Tor::restart`].
+    pub fn restart(&mut self) -> Result<u32> {
+        self.runtime.block_on(self.tor.restart())
+    }
+
+    /// See [`crate::Tor::check_connectivity`].
+    pub fn check_connectivity(&self) -> Result<CheckResult> {
+        self.runtime.block_on(self.tor.check_connectivity())
+    }
+
+    /// See [`crate::Tor::close`].
+    pub fn close(self) -> Result<()> {
+        self.runtime.block_on(self.tor.close())
+    }
+
+    /// See [`crate::Tor::into_detached`].
+    pub fn into_detached(self) -> Result<u32> {
+        self.tor.into_detached()
+    }
+
+    /// See [`crate::Tor::kill`].
+    pub fn kill(&mut self) -> Result<()> {
+        self.tor.kill()
+    }
+
+    /// See [`crate::Tor::wait`].
+    pub fn wait(&mut self) -> Result<ExitStatus> {
+        self.runtime.block_on(self.tor.wait())
+    }
+
+    /// See [`crate::Tor::shutdown`].
+    pub fn shutdown(&mut self) -> Result<ExitStatus> {
+        self.runtime.block_on(self.tor.shutdown())
+    }
+
+    /// See [`crate::Tor::stop`].
+    pub fn stop(&mut self) -> Result<ExitStatus> {
+        self.runtime.block_on(self.tor.stop())
+    }
+}
+
+impl Deref for Tor {
+    type Target = crate::Tor;
+
+    fn deref(&self) -> &Self::Target {
+        &self.tor
+    }
+}
@@ -0,0 +1,1287 @@
+use std::ffi::OsString;
+use std::fmt::Display;
+use std::fs::{read_to_string, write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{Context, Result};
+
+use crate::DEFAULT_SOCKS_PORT;
+
+/// Counter used to generate unique temporary `torrc` file names.
+static TORRC_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Port Tor listens for SOCKS connections on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SocksPort {
+    /// Listen on the provided port.
+    Port(u16),
+    /// Let Tor pick a free port. The chosen port is available through
+    /// `Tor::socks_port` once Tor is running.
+    Auto,
+    /// Listen on a Unix domain socket at the provided path. Tor refuses to
+    /// create it in a directory other users can access.
+    Unix(PathBuf),
+}
+
+impl Display for SocksPort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SocksPort::Port(port) => write!(f, "{port}"),
+            SocksPort::Auto => write!(f, "auto"),
+            SocksPort::Unix(path) => write!(f, "unix:\"{}\"", path.display()),
+        }
+    }
+}
+
+/// Flags added to the SOCKS listener making Tor use separate circuits for
+/// streams which differ in the given property.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SocksIsolation {
+    /// Isolate by SOCKS username and password, see
+    /// `Tor::socks_proxy_url_with_auth`. Tor enables it by default.
+    SocksAuth,
+    ClientAddr,
+    ClientProtocol,
+    DestPort,
+    DestAddr,
+}
+
+impl Display for SocksIsolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let flag = match self {
+            SocksIsolation::SocksAuth => "IsolateSOCKSAuth",
+            SocksIsolation::ClientAddr => "IsolateClientAddr",
+            SocksIsolation::ClientProtocol => "IsolateClientProtocol",
+            SocksIsolation::DestPort => "IsolateDestPort",
+            SocksIsolation::DestAddr => "IsolateDestAddr",
+        };
+
+        write!(f, "{flag}")
+    }
+}
+
+/// Rule deciding which clients may connect to the SOCKS listener, matched
+/// against the client address in order. Patterns follow Tor's policy
+/// syntax, e.g. `127.0.0.1`, `192.168.0.0/16` or `*`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SocksPolicy {
+    Accept(String),
+    Reject(String),
+}
+
+impl SocksPolicy {
+    /// Whether this rule matches every client, i.e. `*` or `*:*`.
+    fn matches_all(&self) -> bool {
+        let (SocksPolicy::Accept(pattern) | SocksPolicy::Reject(pattern)) = self;
+
+        matches!(pattern.trim(), "*" | "*:*")
+    }
+}
+
+impl Display for SocksPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SocksPolicy::Accept(pattern) => write!(f, "accept {pattern}"),
+            SocksPolicy::Reject(pattern) => write!(f, "reject {pattern}"),
+        }
+    }
+}
+
+/// Pluggable transports shipped in the Expert Bundle under
+/// `tor/pluggable_transports`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PluggableTransport {
+    Obfs4,
+    WebTunnel,
+    MeekLite,
+    Snowflake,
+    Conjure,
+}
+
+impl PluggableTransport {
+    /// Transport name used in `ClientTransportPlugin` and `Bridge` lines.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PluggableTransport::Obfs4 => "obfs4",
+            PluggableTransport::WebTunnel => "webtunnel",
+            PluggableTransport::MeekLite => "meek_lite",
+            PluggableTransport::Snowflake => "snowflake",
+            PluggableTransport::Conjure => "conjure",
+        }
+    }
+
+    /// Name of the binary implementing this transport in the bundle.
+    pub fn bin_name(&self) -> String {
+        let name = match self {
+            PluggableTransport::Obfs4
+            | PluggableTransport::WebTunnel
+            | PluggableTransport::MeekLite => "lyrebird",
+            PluggableTransport::Snowflake => "snowflake-client",
+            PluggableTransport::Conjure => "conjure-client",
+        };
+
+        format!("{name}{}", std::env::consts::EXE_SUFFIX)
+    }
+}
+
+impl FromStr for PluggableTransport {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            PluggableTransport::Obfs4,
+            PluggableTransport::WebTunnel,
+            PluggableTransport::MeekLite,
+            PluggableTransport::Snowflake,
+            PluggableTransport::Conjure,
+        ]
+        .into_iter()
+        .find(|transport| transport.name().eq_ignore_ascii_case(s))
+        .with_context(|| format!("Unknown pluggable transport: {s}."))
+    }
+}
+
+impl Display for PluggableTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Severity of the messages Tor logs, from the most to the least verbose.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Notice,
+    Warn,
+    Err,
+}
+
+impl Display for LogLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let level = match self {
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Notice => "notice",
+            LogLevel::Warn => "warn",
+            LogLevel::Err => "err",
+        };
+
+        write!(f, "{level}")
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = anyhow::Error;
+
+    /// Parses the severities as written by Tor, e.g. `warn`.
+    fn from_str(s: &str) -> Result<Self> {
+        let level = match s.to_ascii_lowercase().as_str() {
+            "debug" => LogLevel::Debug,
+            "info" => LogLevel::Info,
+            "notice" => LogLevel::Notice,
+            "warn" => LogLevel::Warn,
+            "err" => LogLevel::Err,
+            _ => anyhow::bail!("Unknown Tor log level \"{s}\"."),
+        };
+
+        Ok(level)
+    }
+}
+
+/// Onion service published by Tor from `dir`, which holds its keys and the
+/// generated `hostname` file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OnionService {
+    pub dir: PathBuf,
+    pub ports: Vec<(u16, String)>,
+}
+
+impl OnionService {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            ports: Vec::new(),
+        }
+    }
+
+    /// Forwards connections to `virtual_port` on the onion address to
+    /// `target`, e.g. `127.0.0.1:8080`.
+    pub fn with_port(mut self, virtual_port: u16, target: impl Into<String>) -> Self {
+        self.ports.push((virtual_port, target.into()));
+        self
+    }
+
+    /// Path to the file Tor writes the `.onion` address into.
+    pub fn hostname_path(&self) -> PathBuf {
+        self.dir.join("hostname")
+    }
+}
+
+/// Tor configuration builder which renders into a `torrc` file.
+///
+/// Refer to the Tor Manual for available options:
+/// https://2019.www.torproject.org/docs/tor-manual.html.en
+#[derive(Clone, Debug, Default)]
+pub struct TorConfig {
+    pub socks_port: Option<SocksPort>,
+    pub socks_isolation: Vec<SocksIsolation>,
+    pub socks_policy: Vec<SocksPolicy>,
+    /// Address the SOCKS listener binds to. Tor binds to `127.0.0.1`
+    /// unless set.
+    pub socks_bind_address: Option<IpAddr>,
+    pub http_tunnel_port: Option<u16>,
+    pub control_port: Option<u16>,
+    /// Address the Control Port binds to. Tor binds to `127.0.0.1` unless
+    /// set.
+    pub control_bind_address: Option<IpAddr>,
+    /// Whether the Control Port may bind to a non-loopback address, see
+    /// [`TorConfig::with_remote_control`].
+    pub remote_control: bool,
+    pub control_socket: Option<PathBuf>,
+    /// Directory Tor keeps its state in. When running through [`Tor`] it
+    /// defaults to a unique temporary directory per instance, so multiple
+    /// instances don't collide.
+    ///
+    /// [`Tor`]: crate::Tor
+    pub data_directory: Option<PathBuf>,
+    pub pluggable_transports: Vec<PluggableTransport>,
+    /// Directory holding the pluggable transport binaries. When running
+    /// through [`Tor`] it defaults to the unpacked bundle's
+    /// `tor/pluggable_transports` directory.
+    ///
+    /// [`Tor`]: crate::Tor
+    pub pluggable_transports_dir: Option<PathBuf>,
+    pub bridges: Vec<String>,
+    /// File holding one bridge line per line, read every time the
+    /// configuration is rendered, along with [`TorConfig::bridges`].
+    pub bridges_file: Option<PathBuf>,
+    /// GeoIP databases Tor maps relays to countries with. When running
+    /// through [`Tor`] they default to the ones shipped in the bundle.
+    ///
+    /// [`Tor`]: crate::Tor
+    pub geoip_file: Option<PathBuf>,
+    pub geoip6_file: Option<PathBuf>,
+    pub entry_countries: Vec<String>,
+    pub exit_countries: Vec<String>,
+    pub exclude_countries: Vec<String>,
+    pub strict_nodes: bool,
+    pub onion_services: Vec<OnionService>,
+    pub options: Vec<(String, String)>,
+    pub log_level: Option<LogLevel>,
+    pub log_file: Option<PathBuf>,
+    pub dormant_canceled_by_startup: bool,
+    pub dormant_timeout_disabled_by_idle_streams: Option<bool>,
+    pub client_only: bool,
+    /// Bandwidth limits in bytes per second.
+    pub bandwidth_rate: Option<u64>,
+    pub bandwidth_burst: Option<u64>,
+    pub relay_bandwidth_rate: Option<u64>,
+    /// Command line arguments passed to Tor as they are, not written into
+    /// the `torrc`.
+    pub extra_args: Vec<String>,
+}
+
+impl TorConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_socks_port(mut self, port: u16) -> Self {
+        self.socks_port = Some(SocksPort::Port(port));
+        self
+    }
+
+    /// Lets Tor pick a free port for its SOCKS listener.
+    pub fn with_socks_port_auto(mut self) -> Self {
+        self.socks_port = Some(SocksPort::Auto);
+        self
+    }
+
+    /// Adds an isolation flag to the SOCKS listener, which listens on port
+    /// 9050 unless configured otherwise.
+    pub fn with_socks_isolation(mut self, isolation: SocksIsolation) -> Self {
+        if !self.socks_isolation.contains(&isolation) {
+            self.socks_isolation.push(isolation);
+        }
+
+        self
+    }
+
+    /// Appends a rule restricting which clients may connect to the SOCKS
+    /// listener, e.g. to only accept the local network when it's bound to a
+    /// non-loopback address. Clients matching no rule are accepted, so end
+    /// with `SocksPolicy::Reject("*".into())` to reject everyone else.
+    ///
+    /// The Control Port has no such policy, it's protected by cookie
+    /// authentication instead.
+    pub fn with_socks_policy(mut self, policy: SocksPolicy) -> Self {
+        self.socks_policy.push(policy);
+        self
+    }
+
+    /// Appends every rule from `policies`, see
+    /// [`TorConfig::with_socks_policy`].
+    pub fn with_socks_policies<I>(self, policies: I) -> Self
+    where
+        I: IntoIterator<Item = SocksPolicy>,
+    {
+        policies.into_iter().fold(self, Self::with_socks_policy)
+    }
+
+    /// Binds the SOCKS listener to `address`, e.g. an interface reachable
+    /// from sibling containers. Binding to a non-loopback address requires a
+    /// [`SocksPolicy`] rejecting `*` after accepting the allowed clients, so
+    /// Tor is not run as an open proxy, see [`TorConfig::validate`].
+    pub fn with_socks_bind_address(mut self, address: IpAddr) -> Self {
+        self.socks_bind_address = Some(address);
+        self
+    }
+
+    /// Binds the Control Port to `address`. Commands are only accepted from
+    /// clients able to read Tor's cookie file regardless. Non-loopback
+    /// addresses also require [`TorConfig::with_remote_control`].
+    pub fn with_control_bind_address(mut self, address: IpAddr) -> Self {
+        self.control_bind_address = Some(address);
+        self
+    }
+
+    /// Allows binding the Control Port to a non-loopback address, exposing
+    /// it to other hosts.
+    pub fn with_remote_control(mut self, remote_control: bool) -> Self {
+        self.remote_control = remote_control;
+        self
+    }
+
+    /// Checks the configuration doesn't expose Tor unintentionally, i.e. a
+    /// SOCKS listener bound to a non-loopback address must come with a
+    /// [`SocksPolicy`] rejecting `*` which no earlier rule accepting `*`
+    /// shadows, and a Control Port bound to one requires
+    /// [`TorConfig::with_remote_control`]. Also checks the bridges file, if
+    /// any, has bridge lines. [`Tor`] refuses to run otherwise.
+    ///
+    /// [`Tor`]: crate::Tor
+    pub fn validate(&self) -> Result<()> {
+        if let Some(path) = &self.bridges_file {
+            Self::read_bridges_file(path)?;
+        }
+
+        if let Some(address) = self.socks_bind_address {
+            // Tor applies the first rule matching a client
+            let closed = matches!(
+                self.socks_policy.iter().find(|policy| policy.matches_all()),
+                Some(SocksPolicy::Reject(_))
+            );
+
+            if !address.is_loopback() && !closed {
+                anyhow::bail!(
+                    "SOCKS listener bound to {address} requires a SocksPolicy rejecting `*`, or Tor would run as an open proxy."
+                );
+            }
+        }
+
+        if let Some(address) = self.control_bind_address {
+            if !address.is_loopback() && !self.remote_control {
+                anyhow::bail!(
+                    "Control Port bound to {address} requires TorConfig::with_remote_control, as it would be reachable from other hosts."
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Binds the SOCKS listener to a Unix domain socket at `path` instead of
+    /// a TCP port, so only processes allowed to access `path` can use it.
+    pub fn with_socks_unix_socket(mut self, path: PathBuf) -> Self {
+        self.socks_port = Some(SocksPort::Unix(path));
+        self
+    }
+
+    /// Enables an HTTP `CONNECT` proxy on the provided port, for clients
+    /// which can't speak SOCKS, e.g. those configured through `http_proxy`.
+    pub fn with_http_tunnel_port(mut self, port: u16) -> Self {
+        self.http_tunnel_port = Some(port);
+        self
+    }
+
+    /// Enables the Tor Control Port on the provided port. Cookie
+    /// authentication is enabled along with it so only processes able to
+    /// read Tor's cookie file can issue commands.
+    pub fn with_control_port(mut self, port: u16) -> Self {
+        self.control_port = Some(port);
+        self
+    }
+
+    /// Enables the Tor Control Port on a Unix domain socket at `path`, which
+    /// unlike a TCP port can be restricted through filesystem permissions.
+    /// Tor refuses to create it in a directory other users can access.
+    /// Cookie authentication is enabled along with it.
+    pub fn with_control_socket(mut self, path: PathBuf) -> Self {
+        self.control_socket = Some(path);
+        self
+    }
+
+    /// Sets the directory Tor keeps its state in. Tor refuses to start when
+    /// another instance uses the same directory.
+    pub fn with_data_directory(mut self, path: PathBuf) -> Self {
+        self.data_directory = Some(path);
+        self
+    }
+
+    /// Registers a `ClientTransportPlugin` for a pluggable transport
+    /// bundled with Tor, required to use bridges of that transport.
+    pub fn with_pluggable_transport(mut self, transport: PluggableTransport) -> Self {
+        if !self.pluggable_transports.contains(&transport) {
+            self.pluggable_transports.push(transport);
+        }
+
+        self
+    }
+
+    /// Sets the directory the pluggable transport binaries are looked up
+    /// in.
+    pub fn with_pluggable_transports_dir(mut self, path: PathBuf) -> Self {
+        self.pluggable_transports_dir = Some(path);
+        self
+    }
+
+    /// Appends a bridge line, e.g. `obfs4 1.2.3.4:443 <fingerprint> cert=...`.
+    /// Lines copied from a `torrc` with a leading `Bridge` keyword are
+    /// accepted as well. Tor only connects through the configured bridges
+    /// once any is given, which may make bootstrapping slower.
+    pub fn with_bridge(mut self, bridge: impl Into<String>) -> Self {
+        let bridge = bridge.into();
+        let line = bridge.trim();
+        let line = line
+            .strip_prefix("Bridge ")
+            .map(str::trim_start)
+            .unwrap_or(line);
+
+        if !line.is_empty() {
+            self.bridges.push(line.to_string());
+        }
+
+        self
+    }
+
+    /// Appends every bridge line from `bridges`, see [`TorConfig::with_bridge`].
+    pub fn with_bridges<I, S>(self, bridges: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        bridges.into_iter().fold(self, Self::with_bridge)
+    }
+
+    /// Reads bridge lines from `path`, one per line in the format accepted
+    /// by [`TorConfig::with_bridge`]. Blank lines and lines starting with `#`
+    /// are skipped. The file is read again every time Tor is started, so
+    /// bridges can be rotated out-of-band before a restart.
+    ///
+    /// Rendering fails once the file can't be read, except through
+    /// [`Display`] which leaves its bridges out, see [`TorConfig::to_torrc`].
+    pub fn with_bridges_file(mut self, path: PathBuf) -> Self {
+        self.bridges_file = Some(path);
+        self
+    }
+
+    /// Bridge lines given inline along with the ones in the bridges file.
+    fn bridge_lines(&self) -> Result<Vec<String>> {
+        let mut bridges = self.bridges.clone();
+
+        if let Some(path) = &self.bridges_file {
+            bridges.extend(Self::read_bridges_file(path)?);
+        }
+
+        Ok(bridges)
+    }
+
+    /// Reads the bridge lines in `path`, failing if there is none.
+    fn read_bridges_file(path: &Path) -> Result<Vec<String>> {
+        let contents = read_to_string(path)
+            .with_context(|| format!("Failed to read bridges file {}.", path.display()))?;
+        let bridges = TorConfig::new()
+            .with_bridges(
+                contents
+                    .lines()
+                    .filter(|line| !line.trim_start().starts_with('#')),
+            )
+            .bridges;
+
+        if bridges.is_empty() {
+            anyhow::bail!("Bridges file {} has no bridge lines.", path.display());
+        }
+
+        Ok(bridges)
+    }
+
+    /// Sets the IPv4 GeoIP database.
+    pub fn with_geoip_file(mut self, path: PathBuf) -> Self {
+        self.geoip_file = Some(path);
+        self
+    }
+
+    /// Sets the IPv6 GeoIP database.
+    pub fn with_geoip6_file(mut self, path: PathBuf) -> Self {
+        self.geoip6_file = Some(path);
+        self
+    }
+
+    /// Only uses guards in the provided countries, given as ISO 3166
+    /// codes, e.g. `de`. Requires the GeoIP databases.
+    pub fn with_entry_countries<I, S>(mut self, countries: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.entry_countries.extend(Self::country_codes(countries));
+        self
+    }
+
+    /// Only uses exits in the provided countries, given as ISO 3166 codes,
+    /// e.g. `us`. Requires the GeoIP databases.
+    pub fn with_exit_countries<I, S>(mut self, countries: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.exit_countries.extend(Self::country_codes(countries));
+        self
+    }
+
+    /// Avoids relays in the provided countries, given as ISO 3166 codes.
+    /// Requires the GeoIP databases.
+    pub fn with_exclude_countries<I, S>(mut self, countries: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.exclude_countries
+            .extend(Self::country_codes(countries));
+        self
+    }
+
+    /// Treats the country restrictions as requirements instead of
+    /// preferences, failing to build circuits rather than using other
+    /// relays.
+    pub fn with_strict_nodes(mut self, strict_nodes: bool) -> Self {
+        self.strict_nodes = strict_nodes;
+        self
+    }
+
+    /// Normalizes country codes, accepting them with or without the braces
+    /// Tor expects.
+    fn country_codes<I, S>(countries: I) -> impl Iterator<Item = String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        countries.into_iter().filter_map(|country| {
+            let country = country
+                .as_ref()
+                .trim()
+                .trim_start_matches('{')
+                .trim_end_matches('}');
+
+            (!country.is_empty()).then(|| country.to_ascii_lowercase())
+        })
+    }
+
+    /// Prefixes `port` with `address`, if any, as expected by Tor's port
+    /// options.
+    fn bind(address: Option<IpAddr>, port: &dyn Display) -> String {
+        match address {
+            Some(IpAddr::V6(address)) => format!("[{address}]:{port}"),
+            Some(address) => format!("{address}:{port}"),
+            None => port.to_string(),
+        }
+    }
+
+    /// Pluggable transports registered explicitly along with the ones
+    /// required by the configured bridges.
+    fn transports(&self, bridges: &[String]) -> Vec<PluggableTransport> {
+        let mut transports = self.pluggable_transports.clone();
+        let required = bridges.iter().filter_map(|bridge| {
+            bridge
+                .split_whitespace()
+                .next()
+                .and_then(|name| name.parse::<PluggableTransport>().ok())
+        });
+
+        for transport in required {
+            if !transports.contains(&transport) {
+                transports.push(transport);
+            }
+        }
+
+        transports
+    }
+
+    /// Publishes an onion service. Its address is available through
+    /// [`Tor::onion_address`] once Tor bootstrapped.
+    ///
+    /// [`Tor::onion_address`]: crate::Tor::onion_address
+    pub fn with_onion_service(mut self, service: OnionService) -> Self {
+        self.onion_services.push(service);
+        self
+    }
+
+    /// Appends a raw `torrc` option, e.g. `("SocksPort", "9150")`.
+    pub fn with_option(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options.push((key.into(), value.into()));
+        self
+    }
+
+    /// Sets the minimum severity Tor logs. Messages are logged to stdout at
+    /// `notice` at least regardless, as bootstrap progress is read from
+    /// them.
+    pub fn with_log_level(mut self, level: LogLevel) -> Self {
+        self.log_level = Some(level);
+        self
+    }
+
+    /// Logs to `path` as well, at the level set through
+    /// [`TorConfig::with_log_level`] or `notice` by default.
+    pub fn with_log_file(mut self, path: PathBuf) -> Self {
+        self.log_file = Some(path);
+        self
+    }
+
+    /// Wakes Tor up on startup if it was dormant when it last ran, instead
+    /// of staying dormant until a client connects through it.
+    pub fn with_dormant_canceled_by_startup(mut self, canceled: bool) -> Self {
+        self.dormant_canceled_by_startup = canceled;
+        self
+    }
+
+    /// Sets whether streams left open without traffic keep Tor from going
+    /// dormant. Tor defaults to `true`.
+    pub fn with_dormant_timeout_disabled_by_idle_streams(mut self, disabled: bool) -> Self {
+        self.dormant_timeout_disabled_by_idle_streams = Some(disabled);
+        self
+    }
+
+    /// Makes sure Tor only acts as a client and never as a relay, even if
+    /// ports such as `ORPort` were configured through other options.
+    pub fn with_client_only(mut self, client_only: bool) -> Self {
+        self.client_only = client_only;
+        self
+    }
+
+    /// Caps the average bandwidth Tor uses, in bytes per second.
+    pub fn with_bandwidth_rate(mut self, bytes_per_second: u64) -> Self {
+        self.bandwidth_rate = Some(bytes_per_second);
+        self
+    }
+
+    /// Caps the bandwidth Tor may burst to, in bytes per second. Tor requires
+    /// it to be at least the bandwidth rate.
+    pub fn with_bandwidth_burst(mut self, bytes_per_second: u64) -> Self {
+        self.bandwidth_burst = Some(bytes_per_second);
+        self
+    }
+
+    /// Caps the bandwidth used for relaying traffic, in bytes per second,
+    /// which only applies when Tor acts as a relay.
+    pub fn with_relay_bandwidth_rate(mut self, bytes_per_second: u64) -> Self {
+        self.relay_bandwidth_rate = Some(bytes_per_second);
+        self
+    }
+
+    /// Passes a one-off command line argument to Tor, e.g. `--quiet`.
+    ///
+    /// Extra arguments are placed before the options rendered from this
+    /// config, so the latter take precedence. Arguments changing where Tor
+    /// logs to, e.g. `--Log "notice file tor.log"`, keep [`Tor`] from
+    /// detecting bootstrap progress.
+    ///
+    /// [`Tor`]: crate::Tor
+    pub fn with_extra_arg(mut self, arg: impl Into<String>) -> Self {
+        self.extra_args.push(arg.into());
+        self
+    }
+
+    /// Appends every argument from `args`, see [`TorConfig::with_extra_arg`].
+    pub fn with_extra_args<I, S>(self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        args.into_iter().fold(self, Self::with_extra_arg)
+    }
+
+    /// Every option as `(key, value)` pairs in the order they are rendered.
+    fn entries(&self, bridges: &[String]) -> Vec<(String, String)> {
+        let mut entries = Vec::new();
+
+        let socks_port = match (&self.socks_port, self.socks_isolation.is_empty()) {
+            (Some(socks_port), _) => Some(socks_port.clone()),
+            (None, true) if self.socks_bind_address.is_none() => None,
+            (None, _) => Some(SocksPort::Port(DEFAULT_SOCKS_PORT)),
+        };
+
+        if let Some(socks_port) = socks_port {
+            let flags = self
+                .socks_isolation
+                .iter()
+                .map(|isolation| format!(" {isolation}"))
+                .collect::<String>();
+            let socks_port = match socks_port {
+                SocksPort::Unix(_) => socks_port.to_string(),
+                _ => Self::bind(self.socks_bind_address, &socks_port),
+            };
+
+            entries.push(("SocksPort".to_string(), format!("{socks_port}{flags}")));
+        }
+
+        entries.extend(
+            self.socks_policy
+                .iter()
+                .map(|policy| ("SocksPolicy".to_string(), policy.to_string())),
+        );
+
+        if let Some(http_tunnel_port) = &self.http_tunnel_port {
+            entries.push(("HTTPTunnelPort".to_string(), http_tunnel_port.to_string()));
+        }
+
+        if let Some(control_port) = &self.control_port {
+            entries.push((
+                "ControlPort".to_string(),
+                Self::bind(self.control_bind_address, control_port),
+            ));
+        }
+
+        if let Some(control_socket) = &self.control_socket {
+            entries.push((
+                "ControlSocket".to_string(),
+                format!("\"{}\"", control_socket.display()),
+            ));
+        }
+
+        if self.control_port.is_some() || self.control_socket.is_some() {
+            entries.push(("CookieAuthentication".to_string(), "1".to_string()));
+        }
+
+        if let Some(data_directory) = &self.data_directory {
+            entries.push((
+                "DataDirectory".to_string(),
+                data_directory.display().to_string(),
+            ));
+        }
+
+        for (key, path) in [
+            ("GeoIPFile", &self.geoip_file),
+            ("GeoIPv6File", &self.geoip6_file),
+        ] {
+            if let Some(path) = path {
+                entries.push((key.to_string(), path.display().to_string()));
+            }
+        }
+
+        for transport in self.transports(bridges) {
+            let bin_path = match &self.pluggable_transports_dir {
+                Some(dir) => dir.join(transport.bin_name()),
+                None => PathBuf::from(transport.bin_name()),
+            };
+
+            entries.push((
+                "ClientTransportPlugin".to_string(),
+                format!("{transport} exec {}", bin_path.display()),
+            ));
+        }
+
+        if !bridges.is_empty() {
+            entries.push(("UseBridges".to_string(), "1".to_string()));
+            entries.extend(
+                bridges
+                    .iter()
+                    .map(|bridge| ("Bridge".to_string(), bridge.clone())),
+            );
+        }
+
+        for (key, countries) in [
+            ("EntryNodes", &self.entry_countries),
+            ("ExitNodes", &self.exit_countries),
+            ("ExcludeNodes", &self.exclude_countries),
+        ] {
+            if !countries.is_empty() {
+                let countries: Vec<String> = countries
+                    .iter()
+                    .map(|country| format!("{{{country}}}"))
+                    .collect();
+
+                entries.push((key.to_string(), countries.join(",")));
+            }
+        }
+
+        if self.strict_nodes {
+            entries.push(("StrictNodes".to_string(), "1".to_string()));
+        }
+
+        for service in &self.onion_services {
+            entries.push((
+                "HiddenServiceDir".to_string(),
+                service.dir.display().to_string(),
+            ));
+            entries.extend(service.ports.iter().map(|(virtual_port, target)| {
+                (
+                    "HiddenServicePort".to_string(),
+                    format!("{virtual_port} {target}"),
+                )
+            }));
+        }
+
+        if self.log_level.is_some() || self.log_file.is_some() {
+            let level = self.log_level.unwrap_or(LogLevel::Notice);
+
+            // any `Log` line replaces Tor's default of logging to stdout
+            entries.push((
+                "Log".to_string(),
+                format!("{} stdout", level.min(LogLevel::Notice)),
+            ));
+
+            if let Some(log_file) = &self.log_file {
+                entries.push((
+                    "Log".to_string(),
+                    format!("{level} file {}", log_file.display()),
+                ));
+            }
+        }
+
+        if self.dormant_canceled_by_startup {
+            entries.push(("DormantCanceledByStartup".to_string(), "1".to_string()));
+        }
+
+        if let Some(disabled) = self.dormant_timeout_disabled_by_idle_streams {
+            entries.push((
+                "DormantTimeoutDisabledByIdleStreams".to_string(),
+                u8::from(disabled).to_string(),
+            ));
+        }
+
+        if self.client_only {
+            entries.push(("ClientOnly".to_string(), "1".to_string()));
+        }
+
+        for (key, rate) in [
+            ("BandwidthRate", self.bandwidth_rate),
+            ("BandwidthBurst", self.bandwidth_burst),
+            ("RelayBandwidthRate", self.relay_bandwidth_rate),
+        ] {
+            if let Some(rate) = rate {
+                entries.push((key.to_string(), format!("{rate} bytes")));
+            }
+        }
+
+        entries.extend(self.options.iter().cloned());
+        entries
+    }
+
+    /// Renders the configuration as command line arguments for the `tor`
+    /// binary, e.g. `--SocksPort 9150`. Fails if the bridges file can't be
+    /// read.
+    pub fn to_args(&self) -> Result<Vec<OsString>> {
+        Ok(self
+            .entries(&self.bridge_lines()?)
+            .into_iter()
+            .flat_map(|(key, value)| [OsString::from(format!("--{key}")), OsString::from(value)])
+            .collect())
+    }
+
+    /// Renders the `torrc` file. Unlike [`Display`], includes the bridges
+    /// file and fails if it can't be read.
+    pub fn to_torrc(&self) -> Result<String> {
+        Ok(Self::render(self.entries(&self.bridge_lines()?)))
+    }
+
+    /// Writes the `torrc` file into the provided path, see
+    /// [`TorConfig::to_torrc`].
+    pub fn write(&self, path: &Path) -> Result<()> {
+        write(path, self.to_torrc()?).context("Failed to write torrc file.")
+    }
+
+    /// Writes the `torrc` file into a unique file in the system's temporary
+    /// directory and returns its path.
+    pub fn write_temp(&self) -> Result<PathBuf> {
+        let path = std::env::temp_dir().join(format!(
+            "torproject-{pid}-{n}.torrc",
+            pid = std::process::id(),
+            n = TORRC_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        self.write(&path)?;
+
+        Ok(path)
+    }
+}
+
+impl TorConfig {
+    fn render(entries: Vec<(String, String)>) -> String {
+        entries
+            .into_iter()
+            .map(|(key, value)| format!("{key} {value}\n"))
+            .collect()
+    }
+}
+
+/// Renders the `torrc` file leaving out the bridges file, as formatting
+/// can't fail, see [`TorConfig::to_torrc`].
+impl Display for TorConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&Self::render(self.entries(&self.bridges)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{read_to_string, remove_file};
+
+    use anyhow::Result;
+
+    use std::ffi::OsString;
+
+    use std::path::PathBuf;
+
+    use super::{
+        LogLevel, OnionService, PluggableTransport, SocksIsolation, SocksPolicy, TorConfig,
+    };
+
+    #[test]
+    fn renders_torrc() {
+        let config = TorConfig::new()
+            .with_option("SocksPort", "9150")
+            .with_option("Log", "notice stdout");
+
+        assert_eq!(config.to_string(), "SocksPort 9150\nLog notice stdout\n");
+    }
+
+    #[test]
+    fn renders_socks_port() {
+        let config = TorConfig::new().with_socks_port(9150);
+        let auto = TorConfig::new().with_socks_port_auto();
+
+        assert_eq!(config.to_string(), "SocksPort 9150\n");
+        assert_eq!(
+            auto.to_args().unwrap(),
+            vec![OsString::from("--SocksPort"), OsString::from("auto")]
+        );
+        assert_eq!(
+            TorConfig::new()
+                .with_socks_unix_socket(PathBuf::from("/run/tor/socks.sock"))
+                .to_string(),
+            "SocksPort unix:\"/run/tor/socks.sock\"\n"
+        );
+    }
+
+    #[test]
+    fn renders_socks_isolation() {
+        let isolated = TorConfig::new()
+            .with_socks_isolation(SocksIsolation::SocksAuth)
+            .with_socks_isolation(SocksIsolation::DestAddr)
+            .with_socks_isolation(SocksIsolation::SocksAuth);
+
+        assert_eq!(
+            isolated.to_string(),
+            "SocksPort 9050 IsolateSOCKSAuth IsolateDestAddr\n"
+        );
+        assert_eq!(
+            isolated.with_socks_port(9150).to_string(),
+            "SocksPort 9150 IsolateSOCKSAuth IsolateDestAddr\n"
+        );
+    }
+
+    #[test]
+    fn renders_socks_policy() {
+        let config = TorConfig::new().with_socks_port(9150).with_socks_policies([
+            SocksPolicy::Accept("127.0.0.1".into()),
+            SocksPolicy::Accept("192.168.0.0/16".into()),
+            SocksPolicy::Reject("*".into()),
+        ]);
+
+        assert_eq!(
+            config.to_string(),
+            "SocksPort 9150\nSocksPolicy accept 127.0.0.1\nSocksPolicy accept 192.168.0.0/16\nSocksPolicy reject *\n"
+        );
+    }
+
+    #[test]
+    fn renders_bind_addresses() -> Result<()> {
+        let config = TorConfig::new()
+            .with_socks_bind_address("10.0.0.2".parse()?)
+            .with_control_port(9051)
+            .with_control_bind_address("::1".parse()?);
+
+        assert_eq!(
+            config.to_string(),
+            "SocksPort 10.0.0.2:9050\nControlPort [::1]:9051\nCookieAuthentication 1\n"
+        );
+        assert_eq!(
+            config
+                .clone()
+                .with_socks_port_auto()
+                .with_socks_isolation(SocksIsolation::DestAddr)
+                .to_string()
+                .lines()
+                .next(),
+            Some("SocksPort 10.0.0.2:auto IsolateDestAddr")
+        );
+        assert!(config.validate().is_err());
+        assert!(config
+            .clone()
+            .with_socks_policy(SocksPolicy::Accept("10.0.0.0/8".into()))
+            .validate()
+            .is_err());
+        for shadowed in ["*", "*:*"] {
+            assert!(config
+                .clone()
+                .with_socks_policies([
+                    SocksPolicy::Accept(shadowed.into()),
+                    SocksPolicy::Reject("*".into()),
+                ])
+                .validate()
+                .is_err());
+        }
+        config
+            .with_socks_policies([
+                SocksPolicy::Accept("10.0.0.0/8".into()),
+                SocksPolicy::Reject("*:*".into()),
+            ])
+            .validate()?;
+        TorConfig::new()
+            .with_socks_bind_address("127.0.0.1".parse()?)
+            .validate()?;
+
+        let control = TorConfig::new()
+            .with_control_port(9051)
+            .with_control_bind_address("0.0.0.0".parse()?);
+
+        assert!(control.validate().is_err());
+        control.with_remote_control(true).validate()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn renders_http_tunnel_port() {
+        let config = TorConfig::new()
+            .with_socks_port(9150)
+            .with_http_tunnel_port(8118);
+
+        assert_eq!(config.to_string(), "SocksPort 9150\nHTTPTunnelPort 8118\n");
+    }
+
+    #[test]
+    fn renders_control_socket() {
+        let config = TorConfig::new().with_control_socket(PathBuf::from("/run/tor/control"));
+
+        assert_eq!(
+            config.to_string(),
+            "ControlSocket \"/run/tor/control\"\nCookieAuthentication 1\n"
+        );
+        assert_eq!(
+            config.with_control_port(9051).to_string(),
+            "ControlPort 9051\nControlSocket \"/run/tor/control\"\nCookieAuthentication 1\n"
+        );
+    }
+
+    #[test]
+    fn renders_control_port() {
+        let config = TorConfig::new().with_control_port(9051);
+
+        assert_eq!(
+            config.to_string(),
+            "ControlPort 9051\nCookieAuthentication 1\n"
+        );
+    }
+
+    #[test]
+    fn renders_data_directory() {
+        let config = TorConfig::new().with_data_directory(PathBuf::from("/var/lib/tor"));
+
+        assert_eq!(
+            config.to_args().unwrap(),
+            vec![
+                OsString::from("--DataDirectory"),
+                OsString::from("/var/lib/tor")
+            ]
+        );
+    }
+
+    #[test]
+    fn renders_pluggable_transports_and_bridges() {
+        let config = TorConfig::new()
+            .with_pluggable_transport(PluggableTransport::Obfs4)
+            .with_pluggable_transport(PluggableTransport::Obfs4)
+            .with_pluggable_transports_dir(PathBuf::from("/pt"))
+            .with_bridge("obfs4 192.0.2.1:443 0123456789ABCDEF cert=abc iat-mode=0");
+        let bin_path = PathBuf::from("/pt").join(PluggableTransport::Obfs4.bin_name());
+
+        assert_eq!(
+            config.to_string(),
+            format!(
+                "ClientTransportPlugin obfs4 exec {}\nUseBridges 1\nBridge obfs4 192.0.2.1:443 0123456789ABCDEF cert=abc iat-mode=0\n",
+                bin_path.display()
+            )
+        );
+    }
+
+    #[test]
+    fn reads_bridges_file() -> Result<()> {
+        let path = std::env::temp_dir().join(format!(
+            "torproject-test-{}-bridges.txt",
+            std::process::id()
+        ));
+        let config = TorConfig::new()
+            .with_bridge("192.0.2.2:443")
+            .with_bridges_file(path.clone());
+
+        assert!(config.validate().is_err());
+
+        std::fs::write(&path, "# rotated daily\n\n   \n")?;
+
+        assert!(config.validate().is_err());
+
+        std::fs::write(
+            &path,
+            "# rotated daily\nBridge obfs4 192.0.2.1:443 0123456789ABCDEF cert=abc iat-mode=0\n\n",
+        )?;
+        config.validate()?;
+
+        assert_eq!(
+            config.to_torrc()?,
+            format!(
+                "ClientTransportPlugin obfs4 exec {}\nUseBridges 1\nBridge 192.0.2.2:443\nBridge obfs4 192.0.2.1:443 0123456789ABCDEF cert=abc iat-mode=0\n",
+                PluggableTransport::Obfs4.bin_name()
+            )
+        );
+        assert_eq!(config.to_args()?.len(), 8);
+
+        std::fs::remove_file(path)?;
+
+        assert!(config.to_torrc().is_err());
+        assert!(config.to_args().is_err());
+        assert!(config.write_temp().is_err());
+        assert_eq!(config.to_string(), "UseBridges 1\nBridge 192.0.2.2:443\n");
+        Ok(())
+    }
+
+    #[test]
+    fn registers_transports_required_by_bridges() {
+        let config = TorConfig::new().with_bridges([
+            "Bridge snowflake 192.0.2.3:80 2B280B23E1107BB62ABFC40DDCC8824814F80A72",
+            "198.51.100.7:9001",
+            "  ",
+        ]);
+        let rendered = config.to_string();
+
+        assert_eq!(
+            config.bridges,
+            vec![
+                "snowflake 192.0.2.3:80 2B280B23E1107BB62ABFC40DDCC8824814F80A72",
+                "198.51.100.7:9001",
+            ]
+        );
+        assert!(rendered.starts_with(&format!(
+            "ClientTransportPlugin snowflake exec {}\nUseBridges 1\n",
+            PluggableTransport::Snowflake.bin_name()
+        )));
+        assert_eq!(rendered.matches("ClientTransportPlugin").count(), 1);
+    }
+
+    #[test]
+    fn renders_onion_services() {
+        let config = TorConfig::new().with_onion_service(
+            OnionService::new(PathBuf::from("/var/lib/tor/web"))
+                .with_port(80, "127.0.0.1:8080")
+                .with_port(443, "127.0.0.1:8443"),
+        );
+
+        assert_eq!(
+            config.to_string(),
+            "HiddenServiceDir /var/lib/tor/web\nHiddenServicePort 80 127.0.0.1:8080\nHiddenServicePort 443 127.0.0.1:8443\n"
+        );
+    }
+
+    #[test]
+    fn renders_geoip_and_country_options() {
+        let config = TorConfig::new()
+            .with_geoip_file(PathBuf::from("/tor/data/geoip"))
+            .with_geoip6_file(PathBuf::from("/tor/data/geoip6"))
+            .with_exit_countries(["US", "{de}"])
+            .with_exclude_countries([" ru "])
+            .with_strict_nodes(true);
+
+        assert_eq!(
+            config.to_string(),
+            "GeoIPFile /tor/data/geoip\nGeoIPv6File /tor/data/geoip6\nExitNodes {us},{de}\nExcludeNodes {ru}\nStrictNodes 1\n"
+        );
+    }
+
+    #[test]
+    fn renders_log_options() {
+        let config = TorConfig::new()
+            .with_log_level(LogLevel::Warn)
+            .with_log_file(PathBuf::from("/var/log/tor.log"));
+
+        assert_eq!(
+            config.to_string(),
+            "Log notice stdout\nLog warn file /var/log/tor.log\n"
+        );
+
+        let config = TorConfig::new().with_log_level(LogLevel::Debug);
+
+        assert_eq!(config.to_string(), "Log debug stdout\n");
+    }
+
+    #[test]
+    fn renders_dormant_options() {
+        let config = TorConfig::new()
+            .with_dormant_canceled_by_startup(true)
+            .with_dormant_timeout_disabled_by_idle_streams(false);
+
+        assert_eq!(
+            config.to_string(),
+            "DormantCanceledByStartup 1\nDormantTimeoutDisabledByIdleStreams 0\n"
+        );
+    }
+
+    #[test]
+    fn renders_client_only_and_bandwidth_limits() {
+        let config = TorConfig::new()
+            .with_client_only(true)
+            .with_bandwidth_rate(1_048_576)
+            .with_bandwidth_burst(2_097_152)
+            .with_relay_bandwidth_rate(0);
+
+        assert_eq!(
+            config.to_string(),
+            "ClientOnly 1\nBandwidthRate 1048576 bytes\nBandwidthBurst 2097152 bytes\nRelayBandwidthRate 0 bytes\n"
+        );
+        assert_eq!(
+            config.to_args().unwrap()[2..4],
+            [
+                OsString::from("--BandwidthRate"),
+                OsString::from("1048576 bytes")
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_torrc_to_temp_file() -> Result<()> {
+        let config = TorConfig::new().with_option("SocksPort", "9150");
+        let path = config.write_temp()?;
+        let other = config.write_temp()?;
+
+        assert_ne!(path, other);
+        assert_eq!(read_to_string(&path)?, "SocksPort 9150\n");
+
+        remove_file(path)?;
+        remove_file(other)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1074 @@
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tracing::debug;
+
+use crate::{Result, TorError};
+
+/// Status code used by Tor to reply successfully to a command.
+const STATUS_OK: u16 = 250;
+
+/// Status code used by Tor to reject a `SETCONF` for an option which can't
+/// change while it runs.
+const STATUS_TRANSITION_NOT_ALLOWED: u16 = 553;
+
+/// Options Tor only reads on startup, rejected by [`TorController::set_conf`]
+/// before reaching Tor.
+const RESTART_ONLY_OPTIONS: &[&str] = &[
+    "CacheDirectory",
+    "DataDirectory",
+    "DisableAllSwap",
+    "DisableDebuggerAttachment",
+    "HardwareAccel",
+    "KeyDirectory",
+    "NoExec",
+    "PidFile",
+    "RunAsDaemon",
+    "Sandbox",
+    "User",
+];
+
+/// Name of the cookie file Tor writes into its data directory.
+const COOKIE_FILE_NAME: &str = "control_auth_cookie";
+
+/// HMAC key used by Tor to prove it knows the cookie during `SAFECOOKIE`
+/// authentication.
+const SAFECOOKIE_SERVER_KEY: &[u8] = b"Tor safe cookie authentication server-to-controller hash";
+
+/// HMAC key used to prove the controller knows the cookie during
+/// `SAFECOOKIE` authentication.
+const SAFECOOKIE_CLIENT_KEY: &[u8] = b"Tor safe cookie authentication controller-to-server hash";
+
+/// Reply to a command issued over the Tor Control Protocol.
+///
+/// Each entry in `lines` holds the text of a reply line without its status
+/// code. Data replies (`250+keyword=`) are folded into a single entry with
+/// the data following the `keyword=` prefix, separated by newlines.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ControlReply {
+    pub status: u16,
+    pub lines: Vec<String>,
+}
+
+impl ControlReply {
+    fn is_ok(&self) -> bool {
+        self.status == STATUS_OK
+    }
+
+    /// Finds the value for `keyword` on a `keyword=value` reply line.
+    fn value(&self, keyword: &str) -> Option<&str> {
+        self.lines.iter().find_map(|line| {
+            line.strip_prefix(keyword)
+                .and_then(|rest| rest.strip_prefix('='))
+        })
+    }
+}
+
+/// Onion service created through `ADD_ONION`, which lives for as long as
+/// the Tor process does.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EphemeralOnionService {
+    pub service_id: String,
+    /// Key the service can be recreated with, in `ADD_ONION` format.
+    pub private_key: Option<String>,
+}
+
+impl EphemeralOnionService {
+    /// The `.onion` address of the service.
+    pub fn address(&self) -> String {
+        format!("{}.onion", self.service_id)
+    }
+}
+
+/// State of a circuit as reported by Tor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CircuitState {
+    Launched,
+    Built,
+    GuardWait,
+    Extended,
+    Failed,
+    Closed,
+    /// State not known to this crate, as reported by Tor.
+    Other(String),
+}
+
+impl CircuitState {
+    fn parse(state: &str) -> Self {
+        match state {
+            "LAUNCHED" => Self::Launched,
+            "BUILT" => Self::Built,
+            "GUARD_WAIT" => Self::GuardWait,
+            "EXTENDED" => Self::Extended,
+            "FAILED" => Self::Failed,
+            "CLOSED" => Self::Closed,
+            state => Self::Other(state.to_string()),
+        }
+    }
+}
+
+/// Relay along the path of a circuit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CircuitRelay {
+    /// Hex-encoded identity fingerprint of the relay, without the `$`
+    /// prefix.
+    pub fingerprint: String,
+    pub nickname: Option<String>,
+}
+
+impl CircuitRelay {
+    /// Parses a path entry in the `$fingerprint~nickname` format, where the
+    /// nickname is optional and may be separated by `=` instead.
+    fn parse(relay: &str) -> Option<Self> {
+        let relay = relay.strip_prefix('$')?;
+        let (fingerprint, nickname) = match relay.split_once(['~', '=']) {
+            Some((fingerprint, nickname)) => (fingerprint, Some(nickname.to_string())),
+            None => (relay, None),
+        };
+
+        Some(Self {
+            fingerprint: fingerprint.to_string(),
+            nickname,
+        })
+    }
+}
+
+/// Circuit as reported by `GETINFO circuit-status`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CircuitInfo {
+    pub id: String,
+    pub state: CircuitState,
+    /// Relays the circuit goes through, from the guard to the last hop.
+    /// Empty while the first hop is being built.
+    pub path: Vec<CircuitRelay>,
+    /// Purpose of the circuit, e.g. `GENERAL` or `HS_CLIENT_REND`.
+    pub purpose: Option<String>,
+    pub build_flags: Vec<String>,
+}
+
+impl CircuitInfo {
+    /// Parses a line of the `circuit-status` reply, e.g. `1 BUILT
+    /// $AAAA~guard,$BBBB~exit BUILD_FLAGS=NEED_CAPACITY PURPOSE=GENERAL`.
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = split_fields(line).into_iter();
+        let id = fields.next()?.to_string();
+        let state = CircuitState::parse(fields.next()?);
+        let mut circuit = Self {
+            id,
+            state,
+            path: Vec::new(),
+            purpose: None,
+            build_flags: Vec::new(),
+        };
+
+        for (index, field) in fields.enumerate() {
+            if index == 0 && (field.starts_with('$') || !field.contains('=')) {
+                circuit.path = field
+                    .split(',')
+                    .map(CircuitRelay::parse)
+                    .collect::<Option<_>>()?;
+                continue;
+            }
+
+            match field.split_once('=') {
+                Some(("PURPOSE", purpose)) => circuit.purpose = Some(purpose.to_string()),
+                Some(("BUILD_FLAGS", flags)) => {
+                    circuit.build_flags = flags.split(',').map(ToOwned::to_owned).collect();
+                }
+                _ => {}
+            }
+        }
+
+        Some(circuit)
+    }
+}
+
+/// Bytes Tor read and wrote since it started, as reported by
+/// `GETINFO traffic/read` and `traffic/written`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TrafficStats {
+    pub read: u64,
+    pub written: u64,
+}
+
+/// Connection to the Tor Control Port, either over TCP or a Unix domain
+/// socket.
+trait ControlStream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> ControlStream for T {}
+
+/// Minimal client for the Tor Control Protocol.
+///
+/// https://spec.torproject.org/control-spec/
+pub struct TorController {
+    stream: BufReader<Box<dyn ControlStream>>,
+    cookie_file: Option<PathBuf>,
+}
+
+impl TorController {
+    /// Connects to the Tor Control Port listening on `addr`. The connection
+    /// must be authenticated using [`TorController::authenticate`] before
+    /// issuing other commands.
+    pub async fn connect(addr: SocketAddr) -> Result<Self> {
+        let stream = TcpStream::connect(addr)
+            .await
+            .with_context(|| format!("Failed to connect to Tor Control Port on {addr}."))
+            .map_err(TorError::Control)?;
+
+        Ok(Self::new(Box::new(stream)))
+    }
+
+    /// Connects to the Tor Control Port listening on the Unix domain socket
+    /// at `path`, see [`TorController::connect`].
+    #[cfg(unix)]
+    pub async fn connect_unix(path: &Path) -> Result<Self> {
+        let stream = tokio::net::UnixStream::connect(path)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to connect to Tor Control Socket at {}.",
+                    path.display()
+                )
+            })
+            .map_err(TorError::Control)?;
+
+        Ok(Self::new(Box::new(stream)))
+    }
+
+    fn new(stream: Box<dyn ControlStream>) -> Self {
+        Self {
+            stream: BufReader::new(stream),
+            cookie_file: None,
+        }
+    }
+
+    /// Reads the authentication cookie from the `control_auth_cookie` file
+    /// in Tor's `DataDirectory` unless Tor reports where its cookie file is.
+    pub fn with_data_directory(mut self, data_directory: &Path) -> Self {
+        self.cookie_file = Some(data_directory.join(COOKIE_FILE_NAME));
+        self
+    }
+
+    /// Authenticates the connection using the first method supported by Tor
+    /// as reported by `PROTOCOLINFO`. Cookie authentication, preferably
+    /// through the `SAFECOOKIE` challenge, reads the cookie file Tor
+    /// reports, password authentication requires `password`.
+    pub async fn authenticate(&mut self, password: Option<&str>) -> Result<()> {
+        let reply = self.command("PROTOCOLINFO 1").await?;
+        let auth = reply
+            .lines
+            .iter()
+            .find_map(|line| line.strip_prefix("AUTH "))
+            .context("Tor did not report supported authentication methods.")
+            .map_err(TorError::Control)?;
+        let methods = Self::auth_field(auth, "METHODS")
+            .unwrap_or_default()
+            .split(',')
+            .collect::<Vec<&str>>();
+
+        let command = if methods.contains(&"NULL") {
+            "AUTHENTICATE".to_string()
+        } else if methods.contains(&"SAFECOOKIE") {
+            let cookie = self.read_cookie(auth).await?;
+
+            format!(
+                "AUTHENTICATE {}",
+                hex(&self.safecookie_challenge(&cookie).await?)
+            )
+        } else if methods.contains(&"COOKIE") {
+            let cookie = self.read_cookie(auth).await?;
+
+            format!("AUTHENTICATE {}", hex(&cookie))
+        } else if let (true, Some(password)) = (methods.contains(&"HASHEDPASSWORD"), password) {
+            ensure_single_line("Control Port password", password)?;
+            format!("AUTHENTICATE {}", quote(password))
+        } else {
+            return Err(TorError::Control(anyhow!(
+                "No supported authentication method available, Tor offers: {auth}"
+            )));
+        };
+
+        self.expect_ok(&command).await?;
+
+        debug!("Authenticated with Tor Control Port.");
+
+        Ok(())
+    }
+
+    /// Reads the cookie file reported on the `PROTOCOLINFO` `AUTH` line, or
+    /// the one in the data directory set on this controller.
+    async fn read_cookie(&self, auth: &str) -> Result<Vec<u8>> {
+        let cookie_file = Self::auth_field(auth, "COOKIEFILE")
+            .map(|path| PathBuf::from(unquote(path)))
+            .or_else(|| self.cookie_file.clone())
+            .context("Tor did not report a cookie file for authentication.")
+            .map_err(TorError::Control)?;
+
+        tokio::fs::read(&cookie_file)
+            .await
+            .with_context(|| format!("Failed to read cookie file {cookie_file:?}."))
+            .map_err(TorError::Control)
+    }
+
+    /// Performs the `AUTHCHALLENGE` exchange, verifying Tor knows `cookie`
+    /// too, and returns the hash to authenticate with.
+    async fn safecookie_challenge(&mut self, cookie: &[u8]) -> Result<[u8; 32]> {
+        let mut client_nonce = [0; 32];
+
+        getrandom::getrandom(&mut client_nonce)
+            .context("Failed to generate nonce for SAFECOOKIE authentication.")
+            .map_err(TorError::Control)?;
+
+        let reply = self
+            .expect_ok(&format!("AUTHCHALLENGE SAFECOOKIE {}", hex(&client_nonce)))
+            .await?;
+        let challenge = reply
+            .lines
+            .iter()
+            .find_map(|line| line.strip_prefix("AUTHCHALLENGE "))
+            .context("Tor did not reply with an authentication challenge.")
+            .map_err(TorError::Control)?;
+        let (Some(server_hash), Some(server_nonce)) = (
+            Self::auth_field(challenge, "SERVERHASH").and_then(unhex),
+            Self::auth_field(challenge, "SERVERNONCE").and_then(unhex),
+        ) else {
+            return Err(TorError::Control(anyhow!(
+                "Malformed authentication challenge from Tor: {challenge}"
+            )));
+        };
+        let message = [cookie, &client_nonce, &server_nonce].concat();
+
+        if !constant_time_eq(&hmac_sha256(SAFECOOKIE_SERVER_KEY, &message), &server_hash) {
+            return Err(TorError::Control(anyhow!(
+                "Tor failed to prove it knows the authentication cookie."
+            )));
+        }
+
+        Ok(hmac_sha256(SAFECOOKIE_CLIENT_KEY, &message))
+    }
+
+    /// Asks Tor to switch to clean circuits for new connections.
+    pub async fn signal_newnym(&mut self) -> Result<()> {
+        self.signal("NEWNYM").await
+    }
+
+    /// Sends a signal to Tor, e.g. `NEWNYM`, `RELOAD` or `SHUTDOWN`.
+    pub async fn signal(&mut self, signal: &str) -> Result<()> {
+        ensure_token("signal", signal, is_keyword_char)?;
+        self.expect_ok(&format!("SIGNAL {signal}")).await?;
+        Ok(())
+    }
+
+    /// Retrieves information from Tor such as `version` or
+    /// `status/bootstrap-phase`.
+    pub async fn get_info(&mut self, keyword: &str) -> Result<String> {
+        ensure_token("GETINFO keyword", keyword, |c| c.is_ascii_graphic())?;
+        let reply = self.expect_ok(&format!("GETINFO {keyword}")).await?;
+
+        reply
+            .value(keyword)
+            .map(ToOwned::to_owned)
+            .with_context(|| format!("Tor did not reply with a value for {keyword}."))
+            .map_err(TorError::Control)
+    }
+
+    /// Lists the circuits Tor currently knows about.
+    pub async fn circuits(&mut self) -> Result<Vec<CircuitInfo>> {
+        let status = self.get_info("circuit-status").await?;
+
+        status
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                CircuitInfo::parse(line)
+                    .with_context(|| format!("Malformed circuit status from Tor: {line}"))
+                    .map_err(TorError::Control)
+            })
+            .collect()
+    }
+
+    /// Retrieves the amount of bytes Tor read and wrote since it started.
+    pub async fn traffic_stats(&mut self) -> Result<TrafficStats> {
+        Ok(TrafficStats {
+            read: self.get_counter("traffic/read").await?,
+            written: self.get_counter("traffic/written").await?,
+        })
+    }
+
+    async fn get_counter(&mut self, keyword: &str) -> Result<u64> {
+        let value = self.get_info(keyword).await?;
+
+        value
+            .trim()
+            .parse()
+            .with_context(|| format!("Tor replied with an invalid value for {keyword}: {value}"))
+            .map_err(TorError::Control)
+    }
+
+    /// Retrieves the values for a configuration option.
+    pub async fn get_conf(&mut self, key: &str) -> Result<Vec<String>> {
+        ensure_token("configuration option name", key, is_keyword_char)?;
+        let reply = self.expect_ok(&format!("GETCONF {key}")).await?;
+
+        Ok(reply
+            .lines
+            .iter()
+            .filter_map(|line| {
+                let (name, value) = line.split_once('=')?;
+                name.eq_ignore_ascii_case(key).then(|| unquote(value))
+            })
+            .collect())
+    }
+
+    /// Changes the value for a configuration option on the running Tor
+    /// instance. Options Tor only reads on startup, e.g. `DataDirectory`,
+    /// are rejected as they require a restart.
+    ///
+    /// Keys must be option names and values can't span several lines, so
+    /// neither can inject another command.
+    pub async fn set_conf(&mut self, key: &str, value: &str) -> Result<()> {
+        ensure_token("configuration option name", key, is_keyword_char)?;
+        ensure_single_line(&format!("Value for {key}"), value)?;
+
+        if RESTART_ONLY_OPTIONS
+            .iter()
+            .any(|option| option.eq_ignore_ascii_case(key))
+        {
+            return Err(TorError::Control(anyhow!(
+                "{key} can't be changed while Tor is running, restart it with an updated TorConfig instead."
+            )));
+        }
+
+        let command = format!("SETCONF {key}={}", quote(value));
+        let reply = self.command(&command).await?;
+
+        if reply.status == STATUS_TRANSITION_NOT_ALLOWED {
+            return Err(TorError::Control(anyhow!(
+                "{key} can't be changed while Tor is running: {message}",
+                message = reply.lines.join(" ")
+            )));
+        }
+
+        Self::ensure_ok(&command, reply)?;
+        Ok(())
+    }
+
+    /// Creates an onion service with a new key forwarding each
+    /// `(virtual_port, target)` pair, e.g. `(80, "127.0.0.1:8080")`. Unless
+    /// `detach` is set the service is removed once this connection closes.
+    pub async fn add_onion(
+        &mut self,
+        ports: &[(u16, String)],
+        detach: bool,
+    ) -> Result<EphemeralOnionService> {
+        let mut command = "ADD_ONION NEW:ED25519-V3".to_string();
+
+        if detach {
+            command.push_str(" Flags=Detach");
+        }
+
+        for (virtual_port, target) in ports {
+            ensure_token("onion service target", target, |c| c.is_ascii_graphic())?;
+            command.push_str(&format!(" Port={virtual_port},{target}"));
+        }
+
+        let reply = self.expect_ok(&command).await?;
+        let service_id = reply
+            .value("ServiceID")
+            .context("Tor did not reply with a ServiceID for the onion service.")
+            .map_err(TorError::Control)?;
+
+        Ok(EphemeralOnionService {
+            service_id: service_id.to_string(),
+            private_key: reply.value("PrivateKey").map(ToOwned::to_owned),
+        })
+    }
+
+    /// Removes an onion service created through [`TorController::add_onion`].
+    pub async fn del_onion(&mut self, service_id: &str) -> Result<()> {
+        ensure_token("onion service ID", service_id, is_keyword_char)?;
+        self.expect_ok(&format!("DEL_ONION {service_id}")).await?;
+        Ok(())
+    }
+
+    /// Sends a raw command and returns Tor's reply.
+    pub async fn command(&mut self, command: &str) -> Result<ControlReply> {
+        self.stream
+            .get_mut()
+            .write_all(format!("{command}\r\n").as_bytes())
+            .await
+            .context("Failed to write to Tor Control Port.")
+            .map_err(TorError::Control)?;
+
+        read_reply(&mut self.stream)
+            .await
+            .map_err(TorError::Control)
+    }
+
+    /// Sends a raw command and fails unless Tor replies with `250`.
+    async fn expect_ok(&mut self, command: &str) -> Result<ControlReply> {
+        let reply = self.command(command).await?;
+
+        Self::ensure_ok(command, reply)
+    }
+
+    /// Fails unless Tor replied to `command` with `250`.
+    fn ensure_ok(command: &str, reply: ControlReply) -> Result<ControlReply> {
+        if !reply.is_ok() {
+            let verb = command.split_whitespace().next().unwrap_or_default();
+            return Err(TorError::Control(anyhow!(
+                "Tor Control Port rejected {verb}: {status} {message}",
+                status = reply.status,
+                message = reply.lines.join(" ")
+            )));
+        }
+
+        Ok(reply)
+    }
+
+    /// Finds a `NAME=value` field on a `PROTOCOLINFO` `AUTH` line.
+    fn auth_field<'a>(auth: &'a str, name: &str) -> Option<&'a str> {
+        let (_, rest) = auth.split_once(&format!("{name}="))?;
+
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"')? + 2;
+            Some(&rest[..end])
+        } else {
+            rest.split_whitespace().next()
+        }
+    }
+}
+
+/// Reads a complete reply from the Tor Control Port.
+async fn read_reply<R: AsyncBufRead + Unpin>(reader: &mut R) -> anyhow::Result<ControlReply> {
+    let mut lines = Vec::new();
+
+    loop {
+        let line = read_line(reader).await?;
+
+        let (Some(status), Some(separator), Some(text)) =
+            (line.get(..3), line.get(3..4), line.get(4..))
+        else {
+            anyhow::bail!("Malformed reply from Tor Control Port: {line}");
+        };
+        let status = status
+            .parse::<u16>()
+            .with_context(|| format!("Malformed reply from Tor Control Port: {line}"))?;
+        let text = text.to_string();
+
+        match separator {
+            " " => {
+                lines.push(text);
+                return Ok(ControlReply { status, lines });
+            }
+            "-" => lines.push(text),
+            "+" => {
+                let mut data = Vec::new();
+
+                loop {
+                    let data_line = read_line(reader).await?;
+
+                    if data_line == "." {
+                        break;
+                    }
+
+                    data.push(
+                        data_line
+                            .strip_prefix('.')
+                            .unwrap_or(&data_line)
+                            .to_string(),
+                    );
+                }
+
+                lines.push(format!("{text}{}", data.join("\n")));
+            }
+            _ => anyhow::bail!("Malformed reply from Tor Control Port: {line}"),
+        }
+    }
+}
+
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> anyhow::Result<String> {
+    let mut line = String::new();
+
+    if reader
+        .read_line(&mut line)
+        .await
+        .context("Failed to read from Tor Control Port.")?
+        == 0
+    {
+        anyhow::bail!("Tor Control Port closed the connection.");
+    }
+
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Splits a reply line on the spaces found outside of quoted strings.
+fn split_fields(line: &str) -> Vec<&str> {
+    let mut fields = Vec::new();
+    let mut start = None;
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for (index, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ' ' if !quoted => {
+                if let Some(start) = start.take() {
+                    fields.push(&line[start..index]);
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        start.get_or_insert(index);
+    }
+
+    if let Some(start) = start {
+        fields.push(&line[start..]);
+    }
+
+    fields
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Reverts [`hex`], failing on odd lengths or non hexadecimal digits.
+fn unhex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+/// HMAC-SHA256 of `message` as defined in RFC 2104.
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    const BLOCK_SIZE: usize = 64;
+
+    let mut block = [0; BLOCK_SIZE];
+
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let pad = |byte: u8| block.map(|key_byte| key_byte ^ byte);
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(message)
+        .finalize();
+
+    Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize()
+        .into()
+}
+
+/// Compares `a` and `b` without returning early on the first mismatch.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Fails unless `token` is non-empty and only made of characters `allowed`
+/// accepts, so formatting it into a command can't inject another one.
+fn ensure_token(kind: &str, token: &str, allowed: fn(char) -> bool) -> Result<()> {
+    if token.is_empty() || !token.chars().all(allowed) {
+        return Err(TorError::Control(anyhow!("Invalid {kind}: {token:?}.")));
+    }
+
+    Ok(())
+}
+
+/// Fails if `value` spans several lines, which [`quote`] doesn't escape.
+fn ensure_single_line(kind: &str, value: &str) -> Result<()> {
+    if value.contains(['\r', '\n']) {
+        return Err(TorError::Control(anyhow!(
+            "{kind} can't contain line breaks."
+        )));
+    }
+
+    Ok(())
+}
+
+/// Whether `c` may appear in a keyword such as an option or signal name.
+fn is_keyword_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Renders `value` as a Control Protocol quoted string.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Reverts [`quote`], values which are not quoted are returned as is.
+fn unquote(value: &str) -> String {
+    match value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+    {
+        Some(value) => value.replace("\\\"", "\"").replace("\\\\", "\\"),
+        None => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    use super::{
+        hex, hmac_sha256, quote, read_reply, split_fields, unhex, unquote, CircuitInfo,
+        CircuitRelay, CircuitState, ControlReply, TorController, TrafficStats,
+        SAFECOOKIE_CLIENT_KEY, SAFECOOKIE_SERVER_KEY,
+    };
+
+    #[tokio::test]
+    async fn reads_replies() -> Result<()> {
+        let mut single: &[u8] = b"250 OK\r\n";
+        let mut multi: &[u8] =
+            b"250-version=0.4.8.13\r\n250+circuit-status=\r\n1 BUILT $A,$B\r\n..dot\r\n.\r\n250 OK\r\n";
+
+        assert_eq!(
+            read_reply(&mut single).await?,
+            ControlReply {
+                status: 250,
+                lines: vec!["OK".into()]
+            }
+        );
+
+        let reply = read_reply(&mut multi).await?;
+
+        assert_eq!(reply.value("version"), Some("0.4.8.13"));
+        assert_eq!(reply.value("circuit-status"), Some("1 BUILT $A,$B\n.dot"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn quotes_values() {
+        assert_eq!(quote(r#"a "b" \c"#), r#""a \"b\" \\c""#);
+        assert_eq!(unquote(&quote(r#"a "b" \c"#)), r#"a "b" \c"#);
+        assert_eq!(unquote("9050"), "9050");
+    }
+
+    #[test]
+    fn parses_circuit_status() {
+        assert_eq!(
+            split_fields(r#"1 BUILT SOCKS_USERNAME="a \"b\" c" PURPOSE=GENERAL"#),
+            vec![
+                "1",
+                "BUILT",
+                r#"SOCKS_USERNAME="a \"b\" c""#,
+                "PURPOSE=GENERAL"
+            ]
+        );
+        assert_eq!(
+            CircuitInfo::parse(
+                "4 BUILT $AAAA~guard,$BBBB=middle,$CCCC BUILD_FLAGS=IS_INTERNAL,NEED_CAPACITY PURPOSE=GENERAL TIME_CREATED=2024-01-01T00:00:00.000000"
+            ),
+            Some(CircuitInfo {
+                id: "4".into(),
+                state: CircuitState::Built,
+                path: vec![
+                    CircuitRelay {
+                        fingerprint: "AAAA".into(),
+                        nickname: Some("guard".into())
+                    },
+                    CircuitRelay {
+                        fingerprint: "BBBB".into(),
+                        nickname: Some("middle".into())
+                    },
+                    CircuitRelay {
+                        fingerprint: "CCCC".into(),
+                        nickname: None
+                    },
+                ],
+                purpose: Some("GENERAL".into()),
+                build_flags: vec!["IS_INTERNAL".into(), "NEED_CAPACITY".into()],
+            })
+        );
+
+        let launched = CircuitInfo::parse("5 LAUNCHED BUILD_FLAGS=NEED_CAPACITY").unwrap();
+
+        assert_eq!(launched.state, CircuitState::Launched);
+        assert!(launched.path.is_empty());
+        assert_eq!(CircuitInfo::parse("6"), None);
+        assert_eq!(CircuitInfo::parse("7 EXTENDED guard"), None);
+    }
+
+    #[test]
+    fn computes_hmac_sha256() {
+        // RFC 4231 test cases 2 and 6
+        assert_eq!(
+            hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            hex(&hmac_sha256(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            )),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+        assert_eq!(unhex("00ff10"), Some(vec![0x00, 0xff, 0x10]));
+        assert_eq!(unhex("0g"), None);
+        assert_eq!(unhex("abc"), None);
+    }
+
+    #[tokio::test]
+    async fn authenticates_with_safecookie() -> Result<()> {
+        let data_directory =
+            std::env::temp_dir().join(format!("torproject-test-{}-safecookie", std::process::id()));
+        let cookie = [7; 32];
+        let server_nonce = [9; 32];
+
+        std::fs::create_dir_all(&data_directory)?;
+        std::fs::write(data_directory.join("control_auth_cookie"), cookie)?;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut stream = BufReader::new(stream);
+            let mut expected = None;
+            let mut line = String::new();
+
+            while stream.read_line(&mut line).await? > 0 {
+                let command = line.trim_end().to_string();
+                let reply = if command == "PROTOCOLINFO 1" {
+                    // no COOKIEFILE, so the data directory is used
+                    "250-AUTH METHODS=COOKIE,SAFECOOKIE\r\n250 OK\r\n".to_string()
+                } else if let Some(client_nonce) = command.strip_prefix("AUTHCHALLENGE SAFECOOKIE ")
+                {
+                    let message =
+                        [&cookie[..], &unhex(client_nonce).unwrap(), &server_nonce].concat();
+
+                    expected = Some(hex(&hmac_sha256(SAFECOOKIE_CLIENT_KEY, &message)));
+
+                    format!(
+                        "250 AUTHCHALLENGE SERVERHASH={} SERVERNONCE={}\r\n",
+                        hex(&hmac_sha256(SAFECOOKIE_SERVER_KEY, &message)),
+                        hex(&server_nonce)
+                    )
+                } else if command.strip_prefix("AUTHENTICATE ") == expected.as_deref() {
+                    "250 OK\r\n".to_string()
+                } else {
+                    "515 Authentication failed\r\n".to_string()
+                };
+
+                stream.get_mut().write_all(reply.as_bytes()).await?;
+                line.clear();
+            }
+
+            Ok::<(), std::io::Error>(())
+        });
+
+        TorController::connect(addr)
+            .await?
+            .with_data_directory(&data_directory)
+            .authenticate(None)
+            .await?;
+
+        drop(server);
+        std::fs::remove_dir_all(data_directory)?;
+        Ok(())
+    }
+
+    #[test]
+    fn finds_auth_fields() {
+        let auth = r#"METHODS=COOKIE,SAFECOOKIE COOKIEFILE="/tmp/tor dir/control_auth_cookie""#;
+
+        assert_eq!(
+            TorController::auth_field(auth, "METHODS"),
+            Some("COOKIE,SAFECOOKIE")
+        );
+        assert_eq!(
+            TorController::auth_field(auth, "COOKIEFILE"),
+            Some(r#""/tmp/tor dir/control_auth_cookie""#)
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn connects_to_control_socket() -> Result<()> {
+        let path = std::env::temp_dir().join(format!(
+            "torproject-test-{}-control.sock",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path)?;
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut stream = BufReader::new(stream);
+            let mut line = String::new();
+
+            while stream.read_line(&mut line).await? > 0 {
+                let reply: &[u8] = match line.trim_end() {
+                    "PROTOCOLINFO 1" => {
+                        b"250-PROTOCOLINFO 1\r\n250-AUTH METHODS=NULL\r\n250 OK\r\n"
+                    }
+                    "GETINFO version" => b"250-version=0.4.8.13\r\n250 OK\r\n",
+                    _ => b"250 OK\r\n",
+                };
+
+                stream.get_mut().write_all(reply).await?;
+                line.clear();
+            }
+
+            Ok::<(), std::io::Error>(())
+        });
+
+        let mut controller = TorController::connect_unix(&path).await?;
+
+        controller.authenticate(None).await?;
+
+        assert_eq!(controller.get_info("version").await?, "0.4.8.13");
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn drives_control_port() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut stream = BufReader::new(stream);
+            let mut commands = Vec::new();
+            let mut line = String::new();
+
+            while stream.read_line(&mut line).await? > 0 {
+                let command = line.trim_end().to_string();
+                let reply: &[u8] = match command.as_str() {
+                    "PROTOCOLINFO 1" => b"250-PROTOCOLINFO 1\r\n250-AUTH METHODS=NULL\r\n250-VERSION Tor=\"0.4.8.13\"\r\n250 OK\r\n",
+                    "GETINFO version" => b"250-version=0.4.8.13\r\n250 OK\r\n",
+                    "GETCONF SocksPort" => b"250 SocksPort=9050\r\n",
+                    "GETINFO traffic/read" => b"250-traffic/read=1024\r\n250 OK\r\n",
+                    "GETINFO traffic/written" => b"250-traffic/written=512\r\n250 OK\r\n",
+                    "GETINFO circuit-status" => {
+                        b"250+circuit-status=\r\n1 BUILT $AAAA~guard,$BBBB~exit PURPOSE=GENERAL\r\n2 LAUNCHED\r\n.\r\n250 OK\r\n"
+                    }
+                    "SETCONF ExitNodes=\"{ar}\"" => b"552 Unrecognized option\r\n",
+                    "SETCONF ORPort=\"9001\"" => {
+                        b"553 Transition not allowed: Changing ORPort is not allowed.\r\n"
+                    }
+                    "ADD_ONION NEW:ED25519-V3 Flags=Detach Port=80,127.0.0.1:8080" => {
+                        b"250-ServiceID=abcdef\r\n250-PrivateKey=ED25519-V3:key\r\n250 OK\r\n"
+                    }
+                    _ => b"250 OK\r\n",
+                };
+
+                stream.get_mut().write_all(reply).await?;
+                commands.push(command);
+                line.clear();
+            }
+
+            Ok::<Vec<String>, anyhow::Error>(commands)
+        });
+
+        let mut controller = TorController::connect(addr).await?;
+
+        controller.authenticate(None).await?;
+        controller.signal_newnym().await?;
+
+        assert_eq!(controller.get_info("version").await?, "0.4.8.13");
+        assert_eq!(controller.get_conf("SocksPort").await?, vec!["9050"]);
+
+        assert_eq!(
+            controller.traffic_stats().await?,
+            TrafficStats {
+                read: 1024,
+                written: 512
+            }
+        );
+
+        let circuits = controller.circuits().await?;
+
+        assert_eq!(circuits.len(), 2);
+        assert_eq!(circuits[0].path[1].nickname.as_deref(), Some("exit"));
+        assert_eq!(circuits[1].state, CircuitState::Launched);
+        assert!(controller.set_conf("ExitNodes", "{ar}").await.is_err());
+
+        for (key, value) in [
+            ("", "1"),
+            ("Exit Nodes", "1"),
+            ("ExitNodes\r\nSIGNAL", "HALT"),
+            ("ExitNodes", "x\r\nSIGNAL HALT"),
+            ("ExitNodes", "x\nSIGNAL HALT"),
+        ] {
+            assert!(controller.set_conf(key, value).await.is_err(), "{key:?}");
+        }
+
+        for target in [
+            "",
+            "127.0.0.1:8080\r\nSIGNAL HALT",
+            "127.0.0.1:8080 Port=81",
+        ] {
+            assert!(controller
+                .add_onion(&[(80, target.to_string())], true)
+                .await
+                .is_err());
+        }
+
+        assert!(controller.signal("NEWNYM\r\nSIGNAL HALT").await.is_err());
+        assert!(controller.del_onion("abcdef\r\nSIGNAL HALT").await.is_err());
+        assert!(controller.get_info("version\r\nSIGNAL HALT").await.is_err());
+        assert!(controller.get_conf("SocksPort\nSIGNAL HALT").await.is_err());
+
+        for key in ["ORPort", "datadirectory"] {
+            let err = controller.set_conf(key, "9001").await.unwrap_err();
+
+            assert!(
+                err.to_string()
+                    .contains("can't be changed while Tor is running"),
+                "{err}"
+            );
+        }
+
+        let onion = controller
+            .add_onion(&[(80, "127.0.0.1:8080".to_string())], true)
+            .await?;
+
+        assert_eq!(onion.address(), "abcdef.onion");
+        assert_eq!(onion.private_key.as_deref(), Some("ED25519-V3:key"));
+
+        drop(controller);
+
+        assert_eq!(
+            server.await??,
+            vec![
+                "PROTOCOLINFO 1",
+                "AUTHENTICATE",
+                "SIGNAL NEWNYM",
+                "GETINFO version",
+                "GETCONF SocksPort",
+                "GETINFO traffic/read",
+                "GETINFO traffic/written",
+                "GETINFO circuit-status",
+                "SETCONF ExitNodes=\"{ar}\"",
+                "SETCONF ORPort=\"9001\"",
+                "ADD_ONION NEW:ED25519-V3 Flags=Detach Port=80,127.0.0.1:8080",
+            ]
+        );
+
+        Ok(())
+    }
+}
@@ -1,18 +1,32 @@
+use std::ffi::OsString;
 use std::fmt::Display;
-use std::fs::{create_dir, remove_file, File};
-use std::io;
-use std::path::PathBuf;
-
-use anyhow::{Context, Result};
+use std::fs::{create_dir_all, remove_dir_all, remove_file, rename, File, OpenOptions};
+use std::io::{self, Read, Seek, Write};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
 use flate2::read::GzDecoder;
-use reqwest::Client;
+use reqwest::header::{HeaderMap, CONTENT_LENGTH, CONTENT_TYPE, RANGE};
+use reqwest::{Client, Proxy, StatusCode};
 use scraper::{Html, Selector};
+use sha2::{Digest, Sha256, Sha512};
 use tar::Archive;
-use tracing::{debug, info};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tracing::{debug, info, info_span, instrument, warn, Instrument};
+use xz2::read::XzDecoder;
 
-use crate::{VersionSelection, DEFAULT_VERSION, DOWNLOAD_DIRECTORY};
+use crate::{
+    Result, TorError, TorVersion, VersionSelection, DEFAULT_VERSION, DOWNLOAD_DIRECTORY,
+    DOWNLOAD_DIRECTORY_PLUGGABLE_TRANSPORTS, DOWNLOAD_DIRECTORY_TOR, TOR_BIN,
+};
 
 /// Tor Build Targets Available
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Target {
     AndroidAarch64,
     AndroidArmv7,
@@ -24,6 +38,99 @@ pub enum Target {
     MacOSX8664,
     WindowsI686,
     WindowsX8664,
+    /// FreeBSD on x86_64. The official archive does not publish Expert
+    /// Bundles for FreeBSD, so this target is only useful with a mirror
+    /// serving `tor-expert-bundle-freebsd-x86_64-<version>.tar.gz`.
+    FreeBSDX8664,
+    /// Target token used verbatim on the archive URL, for platforms not
+    /// covered by the other variants.
+    Custom(String),
+}
+
+impl Target {
+    /// Every target the Tor Project publishes Expert Bundles for.
+    pub const ALL: [Target; 10] = [
+        Target::AndroidAarch64,
+        Target::AndroidArmv7,
+        Target::AndroidX86,
+        Target::AndroidX8664,
+        Target::GnuLinuxI686,
+        Target::GnuLinuxX8664,
+        Target::MacOSAarch64,
+        Target::MacOSX8664,
+        Target::WindowsI686,
+        Target::WindowsX8664,
+    ];
+
+    /// Whether this is the platform this crate was compiled for, or the one
+    /// detected at runtime through [`Target::detect_runtime`], i.e. the only
+    /// targets whose bundle can be run through [`Tor`].
+    ///
+    /// [`Tor`]: crate::Tor
+    pub fn is_host(&self) -> bool {
+        *self == Target::default() || *self == Target::detect_runtime()
+    }
+
+    /// Target matching the machine this process runs on, which differs from
+    /// [`Target::default`] when the process itself is emulated: an x86_64
+    /// build running under Rosetta on Apple Silicon resolves to
+    /// [`Target::MacOSAarch64`], and a 32-bit build on 64-bit Windows to
+    /// [`Target::WindowsX8664`], so the native bundle is preferred.
+    ///
+    /// Falls back to [`Target::default`] when the architecture can't be
+    /// detected. The result is computed once per process.
+    pub fn detect_runtime() -> Target {
+        static RUNTIME_TARGET: OnceLock<Target> = OnceLock::new();
+
+        RUNTIME_TARGET
+            .get_or_init(|| Self::runtime_target().unwrap_or_default())
+            .clone()
+    }
+
+    /// Reports `hw.optional.arm64`, set on Apple Silicon even for processes
+    /// translated by Rosetta.
+    #[cfg(target_os = "macos")]
+    fn runtime_target() -> Option<Target> {
+        let output = std::process::Command::new("sysctl")
+            .args(["-n", "hw.optional.arm64"])
+            .output()
+            .ok()?;
+
+        match String::from_utf8_lossy(&output.stdout).trim() {
+            "1" => Some(Target::MacOSAarch64),
+            _ => Some(Target::MacOSX8664),
+        }
+    }
+
+    /// Reports the machine architecture Windows exposes to every process,
+    /// along with `PROCESSOR_ARCHITEW6432` which is only set for 32-bit
+    /// processes running on WOW64.
+    #[cfg(target_os = "windows")]
+    fn runtime_target() -> Option<Target> {
+        let arch = std::env::var("PROCESSOR_ARCHITEW6432")
+            .or_else(|_| std::env::var("PROCESSOR_ARCHITECTURE"))
+            .ok()?;
+
+        match arch.to_ascii_uppercase().as_str() {
+            "AMD64" => Some(Target::WindowsX8664),
+            "X86" => Some(Target::WindowsI686),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    fn runtime_target() -> Option<Target> {
+        None
+    }
+
+    /// Name of the `tor` binary shipped in this target's Expert Bundle.
+    pub fn tor_bin_name(&self) -> &'static str {
+        match self {
+            Target::WindowsI686 | Target::WindowsX8664 => "tor.exe",
+            Target::Custom(target) if target.starts_with("windows") => "tor.exe",
+            _ => "tor",
+        }
+    }
 }
 
 impl Default for Target {
@@ -57,6 +164,9 @@ impl Default for Target {
 
         #[cfg(all(target_arch = "x86_64", target_os = "windows"))]
         return Self::WindowsX8664;
+
+        #[cfg(all(target_arch = "x86_64", target_os = "freebsd"))]
+        return Self::FreeBSDX8664;
     }
 }
 
@@ -73,17 +183,307 @@ impl Display for Target {
             Target::MacOSX8664 => "macos-x86_64",
             Target::WindowsI686 => "windows-i686",
             Target::WindowsX8664 => "windows-x86_64",
+            Target::FreeBSDX8664 => "freebsd-x86_64",
+            Target::Custom(target) => target,
         };
 
         write!(f, "{target_str}")
     }
 }
 
-#[derive(Default)]
+impl FromStr for Target {
+    type Err = anyhow::Error;
+
+    /// Parses the target names used on the Tor archive (as rendered by
+    /// [`Display`]) as well as Rust target triples.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let target = match s.to_ascii_lowercase().as_str() {
+            "android-aarch64" | "aarch64-linux-android" => Target::AndroidAarch64,
+            "android-armv7" | "armv7-linux-androideabi" => Target::AndroidArmv7,
+            "android-x86" | "i686-linux-android" => Target::AndroidX86,
+            "android-x86_64" | "x86_64-linux-android" => Target::AndroidX8664,
+            "linux-i686" | "i686-unknown-linux-gnu" => Target::GnuLinuxI686,
+            "linux-x86_64" | "x86_64-unknown-linux-gnu" => Target::GnuLinuxX8664,
+            "macos-aarch64" | "aarch64-apple-darwin" => Target::MacOSAarch64,
+            "macos-x86_64" | "x86_64-apple-darwin" => Target::MacOSX8664,
+            "windows-i686" | "i686-pc-windows-msvc" | "i686-pc-windows-gnu" => Target::WindowsI686,
+            "windows-x86_64" | "x86_64-pc-windows-msvc" | "x86_64-pc-windows-gnu" => {
+                Target::WindowsX8664
+            }
+            "freebsd-x86_64" | "x86_64-unknown-freebsd" => Target::FreeBSDX8664,
+            _ => anyhow::bail!(
+                "Unknown target \"{s}\", expected one of: {}.",
+                Target::ALL.map(|target| target.to_string()).join(", ")
+            ),
+        };
+
+        Ok(target)
+    }
+}
+
+/// Official Tor archive the Expert Bundle is downloaded from.
+const DEFAULT_BASE_URL: &str = "https://archive.torproject.org/tor-package-archive/torbrowser";
+
+/// Maximum amount of requests performed at once when checking which
+/// versions have an Expert Bundle available.
+const MAX_CONCURRENT_REQUESTS: usize = 8;
+
+/// Amount of tarballs [`Downloader::download_all`] downloads at once.
+const DEFAULT_PARALLELISM: usize = 4;
+
+/// Time a request to the Tor archive may take, including downloading the
+/// Expert Bundle, before failing.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Time to wait for a connection to the Tor archive to be established.
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// File in the download path caching the version [`VersionSelection::Latest`]
+/// and [`VersionSelection::Stable`] resolved to.
+const RESOLUTION_CACHE_FILE: &str = "resolved-version.json";
+
+/// Environment variable overriding the directory the default download path
+/// is created in, e.g. for read-only home directories in containers.
+const CACHE_DIR_ENV: &str = "RUST_TOR_PROJECT_CACHE_DIR";
+
+/// Amount of times a download is retried after a transient failure.
+const DEFAULT_RETRIES: u32 = 3;
+
+/// Estimated size of the unpacked Expert Bundle relative to its tarball,
+/// used to leave room for unpacking when checking for disk space.
+const UNPACKED_SIZE_RATIO: u64 = 3;
+
+/// Size of the buffer the tarball is read into by
+/// [`Downloader::download_blocking`].
+#[cfg(feature = "blocking")]
+const DOWNLOAD_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Delay before the first retry, doubled on every subsequent attempt.
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Upper bound for the delay between retries, however many are configured.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Digest the tarball is verified with, against the checksum file of the
+/// same algorithm published next to it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    #[default]
+    Sha256,
+    Sha512,
+}
+
+impl ChecksumAlgorithm {
+    /// Extension of the checksum file published next to the tarball.
+    fn extension(self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Sha256 => "sha256sum",
+            ChecksumAlgorithm::Sha512 => "sha512sum",
+        }
+    }
+
+    /// Length of a digest, hex encoded.
+    fn hex_len(self) -> usize {
+        match self {
+            ChecksumAlgorithm::Sha256 => 64,
+            ChecksumAlgorithm::Sha512 => 128,
+        }
+    }
+
+    fn hasher(self) -> ChecksumHasher {
+        match self {
+            ChecksumAlgorithm::Sha256 => ChecksumHasher::Sha256(Sha256::new()),
+            ChecksumAlgorithm::Sha512 => ChecksumHasher::Sha512(Sha512::new()),
+        }
+    }
+}
+
+impl Display for ChecksumAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let algorithm = match self {
+            ChecksumAlgorithm::Sha256 => "SHA-256",
+            ChecksumAlgorithm::Sha512 => "SHA-512",
+        };
+
+        write!(f, "{algorithm}")
+    }
+}
+
+/// Hasher for either [`ChecksumAlgorithm`].
+enum ChecksumHasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl ChecksumHasher {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            ChecksumHasher::Sha256(hasher) => hasher.update(data),
+            ChecksumHasher::Sha512(hasher) => hasher.update(data),
+        }
+    }
+
+    /// Lowercase hex digest of the data hashed so far.
+    fn finalize(self) -> String {
+        match self {
+            ChecksumHasher::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+            ChecksumHasher::Sha512(hasher) => format!("{:x}", hasher.finalize()),
+        }
+    }
+}
+
+impl Write for ChecksumHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Callback invoked as the Tor Expert Bundle is downloaded with the amount
+/// of bytes downloaded so far and the total size, if known.
+pub type DownloadProgress = Arc<dyn Fn(u64, Option<u64>) + Send + Sync>;
+
+/// Settings a [`Downloader`]'s client is built with, to build a
+/// `reqwest::blocking` client alike.
+#[cfg(feature = "blocking")]
+#[derive(Clone)]
+struct ClientSettings {
+    timeout: Duration,
+    connect_timeout: Duration,
+    proxy: Option<Proxy>,
+}
+
+#[cfg(feature = "blocking")]
+impl ClientSettings {
+    /// Builds a `reqwest::blocking` client, which unlike [`Client`] doesn't
+    /// require a Tokio runtime.
+    fn build_blocking(&self) -> anyhow::Result<reqwest::blocking::Client> {
+        let mut client = crate::blocking_client_builder()
+            .timeout(self.timeout)
+            .connect_timeout(self.connect_timeout);
+
+        if let Some(proxy) = self.proxy.clone() {
+            client = client.proxy(proxy);
+        }
+
+        client.build().context("Failed to build HTTP client.")
+    }
+}
+
+/// Tracks the tarball as it's downloaded: checks it starts like an
+/// archive, hashes it and reports progress.
+struct DownloadState<'a> {
+    download_url: &'a str,
+    hasher: ChecksumHasher,
+    downloaded: u64,
+    total: Option<u64>,
+    progress: Option<&'a DownloadProgress>,
+    header: Vec<u8>,
+    header_checked: bool,
+}
+
+impl<'a> DownloadState<'a> {
+    fn new(
+        downloader: &'a Downloader,
+        download_url: &'a str,
+        hasher: ChecksumHasher,
+        downloaded: u64,
+        total: Option<u64>,
+    ) -> Self {
+        Self {
+            download_url,
+            hasher,
+            downloaded,
+            total,
+            progress: downloader.progress.as_ref(),
+            header: Vec::with_capacity(TarballCompression::MAGIC_LEN),
+            // the start of a resumed download was already checked
+            header_checked: downloaded > 0,
+        }
+    }
+
+    /// Records `chunk`, failing if the download turns out not to be an
+    /// archive.
+    fn update(&mut self, chunk: &[u8]) -> anyhow::Result<()> {
+        if !self.header_checked {
+            self.header.extend(
+                chunk
+                    .iter()
+                    .take(TarballCompression::MAGIC_LEN - self.header.len()),
+            );
+
+            if !TarballCompression::is_candidate(&self.header) {
+                anyhow::bail!(
+                    "Origin did not return a gzip or xz archive for {}.",
+                    self.download_url
+                );
+            }
+
+            self.header_checked = TarballCompression::detect(&self.header).is_some();
+        }
+
+        self.hasher.update(chunk);
+        self.downloaded += chunk.len() as u64;
+
+        if let Some(progress) = self.progress {
+            progress(self.downloaded, self.total);
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
 pub struct DownloadOptions {
     pub download_path: Option<PathBuf>,
     pub target: Option<Target>,
     pub version_selection: Option<VersionSelection>,
+    pub verify_checksum: bool,
+    pub checksum_algorithm: ChecksumAlgorithm,
+    pub progress: Option<DownloadProgress>,
+    pub retries: u32,
+    pub proxy: Option<Proxy>,
+    pub client: Option<Client>,
+    pub force_download: bool,
+    pub base_url: String,
+    pub offline: bool,
+    pub timeout: Duration,
+    pub connect_timeout: Duration,
+    pub resolution_ttl: Option<Duration>,
+    pub cache_namespace: String,
+    pub bundle_dir_name: String,
+    pub resume: bool,
+    pub parallelism: usize,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            download_path: None,
+            target: None,
+            version_selection: None,
+            verify_checksum: true,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            progress: None,
+            retries: DEFAULT_RETRIES,
+            proxy: None,
+            client: None,
+            force_download: false,
+            base_url: DEFAULT_BASE_URL.to_string(),
+            offline: false,
+            timeout: DEFAULT_TIMEOUT,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            resolution_ttl: None,
+            cache_namespace: DOWNLOAD_DIRECTORY.to_string(),
+            bundle_dir_name: DOWNLOAD_DIRECTORY_TOR.to_string(),
+            resume: false,
+            parallelism: DEFAULT_PARALLELISM,
+        }
+    }
 }
 
 impl DownloadOptions {
@@ -101,41 +501,432 @@ impl DownloadOptions {
         self
     }
 
+    /// Sets the target from its name on the Tor archive. Names not known to
+    /// [`Target`] are used verbatim through [`Target::Custom`].
+    pub fn with_target_str(mut self, target: &str) -> Self {
+        self.target = Some(
+            target
+                .parse()
+                .unwrap_or_else(|_| Target::Custom(target.to_string())),
+        );
+        self
+    }
+
     pub fn with_version_selection(mut self, selection: VersionSelection) -> Self {
         self.version_selection = Some(selection);
         self
     }
 
+    /// Enables or disables verification of the downloaded tarball against
+    /// the checksum file published next to it, see
+    /// [`DownloadOptions::with_checksum_algorithm`].
+    pub fn with_checksum_verification(mut self, verify_checksum: bool) -> Self {
+        self.verify_checksum = verify_checksum;
+        self
+    }
+
+    /// Sets the digest the tarball is verified with, fetching the
+    /// `.sha256sum` or `.sha512sum` file accordingly. Defaults to SHA-256.
+    pub fn with_checksum_algorithm(mut self, algorithm: ChecksumAlgorithm) -> Self {
+        self.checksum_algorithm = algorithm;
+        self
+    }
+
+    /// Registers a callback to report download progress. The callback
+    /// receives the bytes downloaded so far and the total size as reported
+    /// by the `Content-Length` header.
+    pub fn with_progress<F>(mut self, progress: F) -> Self
+    where
+        F: Fn(u64, Option<u64>) + Send + Sync + 'static,
+    {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Sets how many times a download is retried with exponential backoff
+    /// after timeouts, connection errors or `5xx` responses.
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Routes every request performed by the [`Downloader`] through the
+    /// provided HTTP or SOCKS proxy.
+    pub fn with_proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Performs every request through `client`, e.g. an application-wide
+    /// client with custom TLS roots, instead of building one. The proxy and
+    /// timeouts set on these options are ignored then.
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Downloads the Tor Expert Bundle even if a valid tarball is already
+    /// present in the download path.
+    pub fn with_force_download(mut self, force_download: bool) -> Self {
+        self.force_download = force_download;
+        self
+    }
+
+    /// Downloads from a mirror of the Tor archive instead of the official
+    /// one. Mirrors must keep the archive layout, i.e.
+    /// `<base_url>/<version>/tor-expert-bundle-<target>-<version>.tar.gz`.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Only uses the Tor Expert Bundle already present in the download
+    /// path, without performing any request. [`VersionSelection::Latest`]
+    /// and [`VersionSelection::Stable`] resolve to the highest cached
+    /// version and checksums are not verified.
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Sets the time a request, including downloading the whole Expert
+    /// Bundle, may take before failing. Defaults to 5 minutes.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the time to wait for a connection to be established. Defaults
+    /// to 30 seconds.
+    pub fn with_connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    /// Reuses the version [`VersionSelection::Latest`] or
+    /// [`VersionSelection::Stable`] resolved to for `ttl`, instead of
+    /// querying the Tor archive on every build. The resolution is cached in
+    /// the download path.
+    pub fn with_resolution_ttl(mut self, ttl: Duration) -> Self {
+        self.resolution_ttl = Some(ttl);
+        self
+    }
+
+    /// Sets the name of the directory created in the platform's cache
+    /// directory for the default download path, so applications embedding
+    /// this crate don't share their cache. Defaults to `RustTorProject`.
+    pub fn with_cache_namespace(mut self, namespace: &str) -> Self {
+        self.cache_namespace = namespace.to_string();
+        self
+    }
+
+    /// Sets the name of the directory the Expert Bundle unpacks its `tor`
+    /// binary into. Only needed if the archive layout changes.
+    pub fn with_bundle_dir_name(mut self, name: &str) -> Self {
+        self.bundle_dir_name = name.to_string();
+        self
+    }
+
+    /// Keeps the partially downloaded tarball when a download fails and
+    /// continues it on the next attempt with a `Range` request. Servers
+    /// without range support get the whole tarball downloaded again.
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Sets how many tarballs [`Downloader::download_all`] downloads at
+    /// once. Defaults to 4.
+    pub fn with_parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = parallelism;
+        self
+    }
+
     pub async fn build(self) -> Result<Downloader> {
-        let download_path = self.download_path.unwrap_or_else(|| {
-            Downloader::default_download_path().expect("Failed to get default download path")
+        let download_path = self.resolved_download_path()?;
+        let client = self.build_client()?;
+        let version = match self.known_version(&download_path)? {
+            Some(version) => version,
+            None => {
+                let version = Downloader::resolve_version(
+                    &client,
+                    &self.base_url,
+                    &self.version_selection.clone().unwrap_or_default(),
+                )
+                .await
+                .map_err(TorError::Download)?;
+
+                self.cache_resolution(&download_path, &version);
+                version
+            }
+        };
+
+        Ok(self.into_downloader(download_path, client, version))
+    }
+
+    /// Blocking counterpart of [`DownloadOptions::build`], resolving the
+    /// version through `reqwest::blocking` rather than a Tokio runtime.
+    ///
+    /// Like `reqwest::blocking`, panics when called from within a Tokio
+    /// runtime.
+    #[cfg(feature = "blocking")]
+    pub fn build_blocking(self) -> Result<Downloader> {
+        let download_path = self.resolved_download_path()?;
+        let client = self.build_client()?;
+        let version = match self.known_version(&download_path)? {
+            Some(version) => version,
+            None => {
+                let blocking_client = self
+                    .client_settings()
+                    .build_blocking()
+                    .map_err(TorError::Download)?;
+                let version = Downloader::resolve_version_blocking(
+                    &blocking_client,
+                    &self.base_url,
+                    &self.version_selection.clone().unwrap_or_default(),
+                )
+                .map_err(TorError::Download)?;
+
+                self.cache_resolution(&download_path, &version);
+                version
+            }
+        };
+
+        Ok(self.into_downloader(download_path, client, version))
+    }
+
+    #[cfg(feature = "blocking")]
+    fn client_settings(&self) -> ClientSettings {
+        ClientSettings {
+            timeout: self.timeout,
+            connect_timeout: self.connect_timeout,
+            proxy: self.proxy.clone(),
+        }
+    }
+
+    /// Download path set through [`DownloadOptions::with_download_path`],
+    /// or the cache directory for the configured namespace.
+    fn resolved_download_path(&self) -> Result<PathBuf> {
+        match &self.download_path {
+            Some(download_path) => Ok(download_path.clone()),
+            None => {
+                Downloader::default_download_path(&self.cache_namespace).map_err(TorError::Download)
+            }
+        }
+    }
+
+    /// Client set through [`DownloadOptions::with_client`], or one built
+    /// from the configured timeouts and proxy.
+    fn build_client(&self) -> Result<Client> {
+        if let Some(client) = &self.client {
+            return Ok(client.clone());
+        }
+
+        let mut client = crate::client_builder()
+            .timeout(self.timeout)
+            .connect_timeout(self.connect_timeout);
+
+        if let Some(proxy) = self.proxy.clone() {
+            client = client.proxy(proxy);
+        }
+
+        client
+            .build()
+            .context("Failed to build HTTP client.")
+            .map_err(TorError::Download)
+    }
+
+    /// Version to download when it's known without a request to the Tor
+    /// archive, i.e. resolved within the resolution TTL or, when offline,
+    /// from the bundles cached in `download_path`.
+    fn known_version(&self, download_path: &Path) -> Result<Option<String>> {
+        let version_selection = self.version_selection.clone().unwrap_or_default();
+        let cached_resolution = self.resolution_ttl.and_then(|ttl| {
+            Downloader::read_resolution(download_path, &self.base_url, &version_selection, ttl)
         });
-        let target = self.target.unwrap_or_default();
-        let version_selection = self.version_selection.unwrap_or_default();
-        let version = Downloader::resolve_version(&version_selection).await?;
 
-        Ok(Downloader {
+        match cached_resolution {
+            Some(version) => Ok(Some(version)),
+            None if self.offline => Downloader::resolve_cached_version(
+                download_path,
+                &self.bundle_dir_name,
+                &self.target.clone().unwrap_or_default(),
+                &version_selection,
+            )
+            .map(Some)
+            .map_err(TorError::Download),
+            None => Ok(None),
+        }
+    }
+
+    /// Records the version resolved from the Tor archive, when resolutions
+    /// are cached.
+    fn cache_resolution(&self, download_path: &Path, version: &str) {
+        if self.resolution_ttl.is_some() {
+            Downloader::write_resolution(
+                download_path,
+                &self.base_url,
+                &self.version_selection.clone().unwrap_or_default(),
+                version,
+            );
+        }
+    }
+
+    fn into_downloader(
+        self,
+        download_path: PathBuf,
+        client: Client,
+        version: String,
+    ) -> Downloader {
+        Downloader {
             download_path,
+            target: self.target.unwrap_or_default(),
+            version,
+            verify_checksum: self.verify_checksum,
+            checksum_algorithm: self.checksum_algorithm,
+            progress: self.progress,
+            retries: self.retries,
+            client,
+            #[cfg(feature = "blocking")]
+            client_settings: ClientSettings {
+                timeout: self.timeout,
+                connect_timeout: self.connect_timeout,
+                proxy: self.proxy,
+            },
+            force_download: self.force_download,
+            base_url: self.base_url,
+            offline: self.offline,
+            bundle_dir_name: self.bundle_dir_name,
+            resume: self.resume,
+            parallelism: self.parallelism,
+        }
+    }
+
+    /// Plans the download without performing any request nor writing to
+    /// disk, unlike [`Downloader::plan`] which requires a built downloader.
+    ///
+    /// Resolving [`VersionSelection::Latest`] or [`VersionSelection::Stable`]
+    /// takes a request to the Tor archive, so these only resolve from a
+    /// resolution cached within [`DownloadOptions::with_resolution_ttl`].
+    pub fn plan(&self) -> Result<DownloadPlan> {
+        let download_path = self.resolved_download_path()?;
+        let target = self.target.clone().unwrap_or_default();
+        let version_selection = self.version_selection.clone().unwrap_or_default();
+        let version = match &version_selection {
+            VersionSelection::Version(version) => Some(version.clone()),
+            selection => self.resolution_ttl.and_then(|ttl| {
+                Downloader::read_resolution(&download_path, &self.base_url, selection, ttl)
+            }),
+        };
+        let version = version.ok_or_else(|| {
+            TorError::Download(anyhow::anyhow!(
+                "Resolving {version_selection:?} version requires a request to the Tor archive."
+            ))
+        })?;
+
+        Ok(DownloadPlan::new(
+            &self.base_url,
+            &download_path,
             target,
             version,
-        })
+        ))
+    }
+}
+
+/// A version listed on the Tor archive, as reported by
+/// [`Downloader::available_bundles`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BundleInfo {
+    pub version: String,
+    /// Whether this is a stable release, as opposed to an alpha, beta or
+    /// release candidate.
+    pub stable: bool,
+    /// Whether the archive has an Expert Bundle for the requested target.
+    /// `false` as well when the archive couldn't be reached for this version.
+    pub available: bool,
+    /// Size of the tarball in bytes, when the archive reports it.
+    pub size: Option<u64>,
+}
+
+/// What a [`Downloader`] would download and where, e.g. to report it in a
+/// dry run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DownloadPlan {
+    pub version: String,
+    pub target: Target,
+    pub download_url: String,
+    pub download_tarball_path: PathBuf,
+}
+
+impl DownloadPlan {
+    fn new(base_url: &str, download_path: &Path, target: Target, version: String) -> Self {
+        Self {
+            download_url: Downloader::bundle_url(base_url, &target, &version),
+            download_tarball_path: download_path
+                .join(Downloader::tarball_file_name(&target, &version)),
+            version,
+            target,
+        }
     }
 }
 
 /// Tor Expert Bundle Downloader
 /// https://www.torproject.org/download/tor/
+///
+/// Bundles can be downloaded for any [`Target`], e.g. to ship them in an
+/// installer, but only the host's can be run through [`Tor::from_downloader`].
+///
+/// [`Tor::from_downloader`]: crate::Tor::from_downloader
+#[derive(Clone)]
 pub struct Downloader {
     download_path: PathBuf,
     target: Target,
     version: String,
+    verify_checksum: bool,
+    checksum_algorithm: ChecksumAlgorithm,
+    progress: Option<DownloadProgress>,
+    retries: u32,
+    client: Client,
+    #[cfg(feature = "blocking")]
+    client_settings: ClientSettings,
+    force_download: bool,
+    base_url: String,
+    offline: bool,
+    bundle_dir_name: String,
+    resume: bool,
+    parallelism: usize,
 }
 
 impl Downloader {
     pub fn new() -> Result<Self> {
+        let client = crate::client_builder()
+            .timeout(DEFAULT_TIMEOUT)
+            .connect_timeout(DEFAULT_CONNECT_TIMEOUT)
+            .build()
+            .context("Failed to build HTTP client.")
+            .map_err(TorError::Download)?;
+
         Ok(Self {
-            download_path: Self::default_download_path()?,
+            download_path: Self::default_download_path(DOWNLOAD_DIRECTORY)?,
             target: Target::default(),
             version: DEFAULT_VERSION.to_string(),
+            verify_checksum: true,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            progress: None,
+            retries: DEFAULT_RETRIES,
+            client,
+            #[cfg(feature = "blocking")]
+            client_settings: ClientSettings {
+                timeout: DEFAULT_TIMEOUT,
+                connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+                proxy: None,
+            },
+            force_download: false,
+            base_url: DEFAULT_BASE_URL.to_string(),
+            offline: false,
+            bundle_dir_name: DOWNLOAD_DIRECTORY_TOR.to_string(),
+            resume: false,
+            parallelism: DEFAULT_PARALLELISM,
         })
     }
 
@@ -153,174 +944,2309 @@ impl Downloader {
         &self.version
     }
 
-    /// Downloads the Tor Expert Bundle and returns the path to its assets.
-    pub async fn download(&self) -> Result<()> {
-        let download_url = self.download_url();
-
-        info!(%download_url, "Downloading Tor Expert Bundle.");
+    /// The version parsed as [`TorVersion`], unless a custom version not
+    /// following the archive's scheme was selected.
+    pub fn tor_version(&self) -> Option<TorVersion> {
+        self.version.parse().ok()
+    }
 
-        let bytes = reqwest::get(download_url)
-            .await
-            .context("Failed to download Tor Expert Bundle from origin.")?
-            .bytes()
-            .await
-            .context("Failed to retrieve files from response.")?
-            .to_vec();
+    #[inline]
+    pub fn target(&self) -> &Target {
+        &self.target
+    }
 
-        self.store_downloaded_assets(bytes)?;
-        self.decompress_tarball()?;
+    /// Name of the directory the `tor` binary is unpacked into.
+    #[inline]
+    pub fn bundle_dir_name(&self) -> &str {
+        &self.bundle_dir_name
+    }
 
-        Ok(())
+    /// Reports what [`Downloader::download`] would download and where,
+    /// without performing any request.
+    pub fn plan(&self) -> DownloadPlan {
+        DownloadPlan::new(
+            &self.base_url,
+            &self.download_path,
+            self.target.clone(),
+            self.version.clone(),
+        )
     }
 
-    pub fn download_tarball_path(&self) -> PathBuf {
-        self.download_path.join(self.tarball_name())
+    /// Lists the versions on the Tor archive which have an Expert Bundle for
+    /// `target`, sorted from oldest to newest.
+    pub async fn list_available_versions(&self, target: &Target) -> Result<Vec<String>> {
+        Ok(self
+            .available_bundles(target)
+            .await?
+            .into_iter()
+            .filter_map(|bundle| bundle.available.then_some(bundle.version))
+            .collect())
     }
 
-    fn decompress_tarball(&self) -> Result<()> {
-        let tarball_path = self.download_tarball_path();
-        let tar_gz = File::open(tarball_path)?;
-        let tar = GzDecoder::new(tar_gz);
-        let mut archive = Archive::new(tar);
+    /// Lists every version on the Tor archive, sorted from oldest to newest,
+    /// along with whether it has an Expert Bundle for `target` and its size.
+    ///
+    /// Only listing the archive can fail: a version whose bundle couldn't be
+    /// checked is logged and reported as unavailable.
+    pub async fn available_bundles(&self, target: &Target) -> Result<Vec<BundleInfo>> {
+        let versions = Self::fetch_tor_versions(&self.client, &self.base_url)
+            .await
+            .map(Self::sort_versions)
+            .map_err(TorError::Download)?;
+        let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_REQUESTS));
+        let mut checks = JoinSet::new();
+
+        for (index, version) in versions.iter().enumerate() {
+            let client = self.client.clone();
+            let semaphore = Arc::clone(&semaphore);
+            let bundle_url = Self::bundle_url(&self.base_url, target, version);
+
+            checks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                let checked = match client.head(&bundle_url).send().await {
+                    Ok(response) if response.status().is_success() => {
+                        // `Response::content_length` is always zero for HEAD
+                        // responses, so the header is read directly.
+                        let size = response
+                            .headers()
+                            .get(CONTENT_LENGTH)
+                            .and_then(|length| length.to_str().ok())
+                            .and_then(|length| length.parse().ok());
+
+                        (true, size)
+                    }
+                    Ok(_) => (false, None),
+                    Err(err) => {
+                        warn!(%err, url = %bundle_url, "Failed to check Tor Expert Bundle availability.");
+                        (false, None)
+                    }
+                };
+
+                (index, checked)
+            });
+        }
 
-        info!(download_dir_path=?self.download_path, "Unpacking tarball.");
+        let mut checked = vec![(false, None); versions.len()];
 
-        archive.unpack(&self.download_path)?;
+        while let Some(joined) = checks.join_next().await {
+            match joined {
+                Ok((index, result)) => checked[index] = result,
+                Err(err) => {
+                    warn!(%err, "Failed to check Tor Expert Bundle availability.")
+                }
+            }
+        }
 
-        Ok(())
+        Ok(versions
+            .into_iter()
+            .zip(checked)
+            .map(|(version, (available, size))| BundleInfo {
+                stable: version
+                    .parse::<TorVersion>()
+                    .is_ok_and(|version| version.is_stable()),
+                version,
+                available,
+                size,
+            })
+            .collect())
     }
 
-    #[cfg(any(target_os = "macos", target_os = "windows"))]
-    fn default_download_path() -> Result<PathBuf> {
-        use dirs::cache_dir;
+    /// Downloads the Tor Expert Bundle and returns the paths of the files
+    /// unpacked from it, e.g. to locate the `geoip` files or pluggable
+    /// transports shipped next to the `tor` binary.
+    #[instrument(name = "download", skip(self), fields(version = %self.version, target = %self.target))]
+    pub async fn download(&self) -> Result<Vec<PathBuf>> {
+        if self.offline {
+            return self.use_cached();
+        }
 
-        let mut download_path =
-            cache_dir().context("No cache directory available on this platform.")?;
-        download_path.push(DOWNLOAD_DIRECTORY);
-        Ok(download_path)
+        self.fetch_tarball().await?;
+        self.unpack()
     }
 
-    #[cfg(target_os = "linux")]
-    fn default_download_path() -> Result<PathBuf> {
-        use dirs::home_dir;
+    /// Downloads the tarballs for every target in `targets` concurrently,
+    /// sharing this downloader's client and settings, and returns the path
+    /// to each tarball in the same order. Tarballs are not unpacked, as
+    /// only the host's bundle could be run.
+    ///
+    /// A failing target doesn't abort the others.
+    pub async fn download_all(&self, targets: &[Target]) -> Vec<(Target, Result<PathBuf>)> {
+        let semaphore = Arc::new(Semaphore::new(self.parallelism.max(1)));
+        let mut downloads = JoinSet::new();
+
+        for (index, target) in targets.iter().enumerate() {
+            let semaphore = Arc::clone(&semaphore);
+            let downloader = Downloader {
+                target: target.clone(),
+                ..self.clone()
+            };
+            let span = info_span!("download", version = %self.version, %target);
+
+            downloads.spawn(
+                async move {
+                    let _permit = semaphore.acquire_owned().await;
+                    let downloaded = downloader
+                        .fetch_tarball()
+                        .await
+                        .map(|_| downloader.download_tarball_path());
+
+                    (index, downloaded)
+                }
+                .instrument(span),
+            );
+        }
 
-        let mut download_path =
-            home_dir().context("No home directory available on this platform.")?;
-        download_path.push(DOWNLOAD_DIRECTORY);
-        Ok(download_path)
-    }
+        let mut results: Vec<_> = targets
+            .iter()
+            .map(|target| {
+                let aborted =
+                    anyhow::anyhow!("Download of Tor Expert Bundle for {target} aborted.");
 
-    fn download_url(&self) -> String {
-        format!("https://archive.torproject.org/tor-package-archive/torbrowser/{version}/tor-expert-bundle-{target}-{version}.tar.gz",
-          target=self.target,
-          version=self.version)
+                (target.clone(), Err(TorError::Download(aborted)))
+            })
+            .collect();
+
+        while let Some(downloaded) = downloads.join_next().await {
+            if let Ok((index, downloaded)) = downloaded {
+                results[index].1 = downloaded;
+            }
+        }
+
+        results
     }
 
-    fn store_downloaded_assets(&self, bytes: Vec<u8>) -> Result<()> {
-        let download_path = self.download_path.clone();
+    /// Makes sure a verified tarball is present in the download path,
+    /// downloading it unless cached.
+    async fn fetch_tarball(&self) -> Result<()> {
+        if self.offline {
+            let download_tarball_path = self.download_tarball_path();
 
-        if !download_path.exists() {
-            create_dir(&download_path).context("Failed to create download directory.")?;
+            if download_tarball_path.exists() {
+                return Ok(());
+            }
+
+            return Err(TorError::Download(anyhow::anyhow!(
+                "Offline mode is enabled but no Tor Expert Bundle was found at {}.",
+                download_tarball_path.display()
+            )));
         }
 
-        info!(?download_path, "Storing Tor Artifacts.");
+        if !self.force_download && self.is_cached().await.map_err(TorError::Download)? {
+            info!(download_tarball_path=%self.download_tarball_path().display(), "Using cached Tor Expert Bundle.");
+            return Ok(());
+        }
 
-        let mut bytes = bytes.as_slice();
-        let download_tarball_path = self.download_tarball_path();
+        let download_url = self.download_url();
 
-        if download_tarball_path.exists() {
-            debug!(download_tarball_path=%download_tarball_path.display(), "Found output file tarball. Clearing.");
-            remove_file(&download_tarball_path)
-                .context("Failed to delete previous Tor Cached installation.")?;
-        }
+        info!(%download_url, "Downloading Tor Expert Bundle.");
 
-        let mut output = File::create_new(&download_tarball_path)
-            .context("Failed to create output tarball file.")?;
+        let mut attempt = 0;
+        let digest = loop {
+            match self.fetch_bundle(&download_url).await {
+                Ok(digest) => break digest,
+                Err(err) => {
+                    let delay = self.retry_after(err, attempt)?;
+                    attempt += 1;
 
-        io::copy(&mut bytes, &mut output).context("Failed to copy output bytes.")?;
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        };
+        let expected = match self.verify_checksum {
+            true => Some(self.fetch_checksum().await),
+            false => None,
+        };
 
-        Ok(())
+        self.store_download(&digest, expected)
     }
 
-    fn tarball_name(&self) -> String {
-        format!(
-            "tor-expert-bundle-{target}-{version}.tar.gz",
-            target = self.target,
-            version = self.version
-        )
-    }
+    /// Blocking counterpart of [`Downloader::download`], performing requests
+    /// through `reqwest::blocking` rather than a Tokio runtime.
+    ///
+    /// The client set through [`DownloadOptions::with_client`] can only run
+    /// on a Tokio runtime, so requests are made through a client built from
+    /// the configured timeouts and proxy instead. Like `reqwest::blocking`,
+    /// panics when called from within a Tokio runtime.
+    #[cfg(feature = "blocking")]
+    pub fn download_blocking(&self) -> Result<Vec<PathBuf>> {
+        if self.offline {
+            return self.use_cached();
+        }
 
-    async fn fetch_tor_versions() -> Result<Vec<String>> {
-        let client = Client::new();
-        let response = client
-            .get("https://archive.torproject.org/tor-package-archive/torbrowser/")
-            .send()
-            .await
-            .context("Failed to fetch Tor versions")?;
+        let client = self
+            .client_settings
+            .build_blocking()
+            .map_err(TorError::Download)?;
 
-        let html = response.text().await?;
-        let document = Html::parse_document(&html);
+        self.fetch_tarball_blocking(&client)?;
+        self.unpack()
+    }
+
+    /// Blocking counterpart of [`Downloader::fetch_tarball`].
+    #[cfg(feature = "blocking")]
+    fn fetch_tarball_blocking(&self, client: &reqwest::blocking::Client) -> Result<()> {
+        if !self.force_download
+            && self
+                .is_cached_blocking(client)
+                .map_err(TorError::Download)?
+        {
+            info!(download_tarball_path=%self.download_tarball_path().display(), "Using cached Tor Expert Bundle.");
+            return Ok(());
+        }
+
+        let download_url = self.download_url();
+
+        info!(%download_url, "Downloading Tor Expert Bundle.");
+
+        let mut attempt = 0;
+        let digest = loop {
+            match self.fetch_bundle_blocking(client, &download_url) {
+                Ok(digest) => break digest,
+                Err(err) => {
+                    let delay = self.retry_after(err, attempt)?;
+                    attempt += 1;
+
+                    std::thread::sleep(delay);
+                }
+            }
+        };
+        let expected = match self.verify_checksum {
+            true => Some(self.fetch_checksum_blocking(client)),
+            false => None,
+        };
+
+        self.store_download(&digest, expected)
+    }
+
+    /// Handles a failed download attempt, returning how long to wait before
+    /// retrying it or the error to fail with.
+    fn retry_after(&self, err: anyhow::Error, attempt: u32) -> Result<Duration> {
+        if attempt < self.retries && Self::is_transient(&err) {
+            let delay = Self::retry_delay(attempt);
+
+            warn!(%err, attempt = attempt + 1, retries = self.retries, ?delay, "Failed to download Tor Expert Bundle. Retrying.");
+            return Ok(delay);
+        }
+
+        if !self.resume {
+            self.remove_download_part();
+        }
+
+        Err(match err.downcast::<TorError>() {
+            Ok(err) => err,
+            Err(err) => {
+                TorError::Download(err.context("Failed to download Tor Expert Bundle from origin."))
+            }
+        })
+    }
+
+    /// Verifies the downloaded tarball against the `expected` checksum,
+    /// unless checksum verification is disabled, and moves it into place.
+    fn store_download(&self, digest: &str, expected: Option<anyhow::Result<String>>) -> Result<()> {
+        if let Some(expected) = expected {
+            let verified = expected
+                .map_err(TorError::Download)
+                .and_then(|expected| Self::verify_checksum(digest, &expected));
+
+            if verified.is_err() {
+                self.remove_download_part();
+            }
+
+            verified?;
+        }
+
+        self.store_downloaded_assets().map_err(TorError::Download)?;
+
+        if self.verify_checksum {
+            self.write_verified_marker(digest);
+        }
+
+        Ok(())
+    }
+
+    /// Unpacks the cached tarball, or uses the bundle unpacked on a previous
+    /// run if the tarball is gone, without performing any request.
+    fn use_cached(&self) -> Result<Vec<PathBuf>> {
+        let download_tarball_path = self.download_tarball_path();
+
+        if download_tarball_path.exists() {
+            info!(download_tarball_path=%download_tarball_path.display(), "Using cached Tor Expert Bundle.");
+            return self.unpack();
+        }
+
+        if self.tor_bin_path().is_file() {
+            return self
+                .validate_unpacked()
+                .and_then(|_| Self::list_files(&self.unpack_path()))
+                .map_err(TorError::Unpack);
+        }
+
+        Err(TorError::Download(anyhow::anyhow!(
+            "Offline mode is enabled but no Tor Expert Bundle was found at {}.",
+            download_tarball_path.display()
+        )))
+    }
+
+    fn unpack(&self) -> Result<Vec<PathBuf>> {
+        let unpacked_files = match self.decompress_tarball().and_then(|unpacked_files| {
+            self.set_executable_permissions()?;
+            self.validate_unpacked()?;
+            Ok(unpacked_files)
+        }) {
+            Ok(unpacked_files) => unpacked_files,
+            Err(err) => {
+                self.remove_tarball();
+                return Err(TorError::Unpack(err));
+            }
+        };
+
+        self.remove_legacy_layout();
+
+        Ok(unpacked_files)
+    }
+
+    /// Every file within `dir`, walking into subdirectories.
+    fn list_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+
+        for entry in std::fs::read_dir(dir).context("Failed to list unpacked files.")? {
+            let path = entry.context("Failed to list unpacked files.")?.path();
+
+            if path.is_dir() {
+                files.extend(Self::list_files(&path)?);
+            } else {
+                files.push(path);
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Directory the Tor Expert Bundle is unpacked into. It is scoped by
+    /// version so multiple versions can be cached side by side.
+    pub fn unpack_path(&self) -> PathBuf {
+        self.download_path.join(&self.version)
+    }
+
+    /// Removes the bundle unpacked by previous releases of this crate
+    /// directly into the download path.
+    fn remove_legacy_layout(&self) {
+        let legacy_path = self.download_path.join(DOWNLOAD_DIRECTORY_TOR);
+
+        if !legacy_path.join(TOR_BIN).is_file() {
+            return;
+        }
+
+        debug!(legacy_path=%legacy_path.display(), "Removing Tor Expert Bundle unpacked with the legacy layout.");
+
+        if let Err(err) = remove_dir_all(&legacy_path) {
+            warn!(%err, legacy_path=%legacy_path.display(), "Failed to remove legacy Tor Expert Bundle.");
+        }
+    }
+
+    /// Directory holding the `tor` binary once the Expert Bundle is
+    /// unpacked.
+    pub fn tor_dir_path(&self) -> PathBuf {
+        self.unpack_path().join(&self.bundle_dir_name)
+    }
+
+    /// Path to the `tor` binary once the Expert Bundle is unpacked.
+    pub fn tor_bin_path(&self) -> PathBuf {
+        self.tor_dir_path().join(self.target.tor_bin_name())
+    }
+
+    /// Removes the artifacts of this version: its tarball, along with any
+    /// partial download or verified marker, and the unpacked bundle. Other
+    /// versions cached in the download path are left untouched.
+    pub fn clear_cache(&self) -> Result<()> {
+        let mut components = Path::new(&self.version).components();
+
+        if !matches!(
+            (components.next(), components.next()),
+            (Some(Component::Normal(_)), None)
+        ) {
+            return Err(TorError::Other(anyhow::anyhow!(
+                "Version {} is not a valid cache directory name.",
+                self.version
+            )));
+        }
+
+        let span = info_span!("clear_cache", version = %self.version);
+        let _enter = span.enter();
+
+        for path in [
+            self.download_tarball_path(),
+            self.download_part_path(),
+            self.verified_marker_path(),
+        ] {
+            Self::remove_cached(&path).map_err(TorError::Other)?;
+        }
+
+        let unpack_path = self.unpack_path();
+
+        if unpack_path.join(&self.bundle_dir_name).is_dir() {
+            Self::remove_cached(&unpack_path).map_err(TorError::Other)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes every artifact cached in the download path, for any version
+    /// and target, as well as the cached version resolution. Entries not
+    /// created by this crate are left untouched, so a download path shared
+    /// with other files is safe to clear.
+    pub fn clear_all(&self) -> Result<()> {
+        let entries = match std::fs::read_dir(&self.download_path) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => {
+                return Err(TorError::Other(
+                    anyhow::Error::new(err).context("Failed to read download directory."),
+                ))
+            }
+        };
+
+        info!(download_path=%self.download_path.display(), "Clearing cached Tor Expert Bundles.");
+
+        for entry in entries {
+            let path = entry
+                .context("Failed to read download directory.")
+                .map_err(TorError::Other)?
+                .path();
+
+            if self.is_cached_artifact(&path) {
+                Self::remove_cached(&path).map_err(TorError::Other)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether `path`, an entry of the download path, was created by this
+    /// crate: a tarball or one of its companion files, an unpacked bundle,
+    /// the legacy layout or the cached version resolution.
+    fn is_cached_artifact(&self, path: &Path) -> bool {
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            return false;
+        };
+
+        if path.is_dir() {
+            return path.join(&self.bundle_dir_name).is_dir()
+                || (name == DOWNLOAD_DIRECTORY_TOR && path.join(TOR_BIN).is_file());
+        }
+
+        name == RESOLUTION_CACHE_FILE || name.starts_with("tor-expert-bundle-")
+    }
+
+    /// Removes the file or directory at `path`, if any.
+    fn remove_cached(path: &Path) -> anyhow::Result<()> {
+        let removed = if path.is_dir() {
+            remove_dir_all(path)
+        } else {
+            remove_file(path)
+        };
+
+        match removed {
+            Ok(()) => {
+                debug!(path=%path.display(), "Removed cached artifact.");
+                Ok(())
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err).with_context(|| format!("Failed to remove {}.", path.display())),
+        }
+    }
+
+    /// Marks the `tor` binary and the bundled pluggable transports as
+    /// executable, as the tarball permissions may not survive unpacking
+    /// depending on the umask.
+    #[cfg(unix)]
+    fn set_executable_permissions(&self) -> anyhow::Result<()> {
+        use std::fs::{read_dir, set_permissions};
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut executables = vec![self.tor_bin_path()];
+        let pluggable_transports_path = self
+            .tor_dir_path()
+            .join(DOWNLOAD_DIRECTORY_PLUGGABLE_TRANSPORTS);
+
+        if pluggable_transports_path.is_dir() {
+            for entry in read_dir(&pluggable_transports_path)
+                .context("Failed to read pluggable transports directory.")?
+            {
+                let path = entry
+                    .context("Failed to read pluggable transports directory.")?
+                    .path();
+
+                // skip documentation and configuration files
+                if path.is_file() && path.extension().is_none() {
+                    executables.push(path);
+                }
+            }
+        }
+
+        for path in executables.into_iter().filter(|path| path.is_file()) {
+            let mut permissions = path
+                .metadata()
+                .with_context(|| format!("Failed to read metadata for {}.", path.display()))?
+                .permissions();
+
+            permissions.set_mode(permissions.mode() | 0o111);
+            set_permissions(&path, permissions)
+                .with_context(|| format!("Failed to mark {} as executable.", path.display()))?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn set_executable_permissions(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Checks the unpacked bundle contains an executable Tor binary.
+    fn validate_unpacked(&self) -> anyhow::Result<()> {
+        let tor_bin_path = self.tor_bin_path();
+
+        if !tor_bin_path.is_file() {
+            anyhow::bail!(
+                "Tor Expert Bundle did not contain a Tor binary at {}.",
+                tor_bin_path.display()
+            );
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = tor_bin_path
+                .metadata()
+                .context("Failed to read Tor binary metadata.")?
+                .permissions()
+                .mode();
+
+            if mode & 0o111 == 0 {
+                anyhow::bail!(
+                    "Tor binary at {} is not executable.",
+                    tor_bin_path.display()
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether the tarball is already present in the download path and, when
+    /// checksum verification is enabled, its digest matches the published
+    /// one.
+    async fn is_cached(&self) -> anyhow::Result<bool> {
+        if !self.download_tarball_path().exists() {
+            return Ok(false);
+        }
+
+        if !self.verify_checksum {
+            return Ok(true);
+        }
+
+        self.matches_checksum(&self.fetch_checksum().await?)
+    }
+
+    /// Blocking counterpart of [`Downloader::is_cached`].
+    #[cfg(feature = "blocking")]
+    fn is_cached_blocking(&self, client: &reqwest::blocking::Client) -> anyhow::Result<bool> {
+        if !self.download_tarball_path().exists() {
+            return Ok(false);
+        }
+
+        if !self.verify_checksum {
+            return Ok(true);
+        }
+
+        self.matches_checksum(&self.fetch_checksum_blocking(client)?)
+    }
+
+    /// Whether the cached tarball's digest is `expected`, recording it in
+    /// the verified marker so it isn't hashed again.
+    fn matches_checksum(&self, expected: &str) -> anyhow::Result<bool> {
+        let (actual, hashed) = match self.read_verified_marker() {
+            Some(digest) => (digest, false),
+            None => (
+                Self::file_digest(&self.download_tarball_path(), self.checksum_algorithm)?,
+                true,
+            ),
+        };
+
+        if actual != expected {
+            debug!(%expected, %actual, "Cached Tor Expert Bundle checksum mismatch.");
+            return Ok(false);
+        }
+
+        if hashed {
+            self.write_verified_marker(&actual);
+        }
+
+        Ok(true)
+    }
+
+    /// Marker next to the tarball recording its digest once verified, so
+    /// an unchanged tarball isn't hashed again on every run.
+    fn verified_marker_path(&self) -> PathBuf {
+        let mut verified_marker_path = self.download_tarball_path().into_os_string();
+        verified_marker_path.push(".verified");
+        PathBuf::from(verified_marker_path)
+    }
+
+    /// Size and modification time of the tarball, which invalidate the
+    /// verified marker when they change.
+    fn tarball_fingerprint(&self) -> anyhow::Result<(u64, u64)> {
+        let metadata = std::fs::metadata(self.download_tarball_path())?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+
+        Ok((metadata.len(), modified))
+    }
+
+    /// Digest recorded by the verified marker, if the tarball didn't change
+    /// since.
+    fn read_verified_marker(&self) -> Option<String> {
+        let contents = std::fs::read_to_string(self.verified_marker_path()).ok()?;
+        let marker: serde_json::Value = serde_json::from_str(&contents).ok()?;
+        let (size, modified) = self.tarball_fingerprint().ok()?;
+
+        if marker.get("size")?.as_u64()? != size || marker.get("modified")?.as_u64()? != modified {
+            debug!("Cached Tor Expert Bundle changed since it was verified.");
+            return None;
+        }
+
+        // markers written before the algorithm was recorded hold SHA-256
+        let algorithm = marker
+            .get("algorithm")
+            .and_then(serde_json::Value::as_str)
+            .unwrap_or(ChecksumAlgorithm::Sha256.extension());
+
+        if algorithm != self.checksum_algorithm.extension() {
+            return None;
+        }
+
+        let digest = marker.get("digest")?.as_str()?.to_string();
+
+        debug!(%digest, "Using verified marker for cached Tor Expert Bundle.");
+
+        Some(digest)
+    }
+
+    /// Records `digest` as the verified digest of the tarball.
+    fn write_verified_marker(&self, digest: &str) {
+        let verified_marker_path = self.verified_marker_path();
+        let written = self.tarball_fingerprint().and_then(|(size, modified)| {
+            let marker = serde_json::json!({
+                "algorithm": self.checksum_algorithm.extension(),
+                "digest": digest,
+                "size": size,
+                "modified": modified,
+            });
+
+            std::fs::write(&verified_marker_path, marker.to_string()).map_err(Into::into)
+        });
+
+        if let Err(err) = written {
+            warn!(%err, verified_marker_path=%verified_marker_path.display(), "Failed to write verified marker.");
+        }
+    }
+
+    fn file_digest(path: &Path, algorithm: ChecksumAlgorithm) -> anyhow::Result<String> {
+        let mut file = File::open(path).context("Failed to open cached tarball.")?;
+        let mut hasher = algorithm.hasher();
+
+        io::copy(&mut file, &mut hasher).context("Failed to read cached tarball.")?;
+
+        Ok(hasher.finalize())
+    }
+
+    /// Streams the tarball into the temporary download file, hashing it as
+    /// it is written, and returns its digest. When resuming, the bytes
+    /// already in the temporary file are hashed and only the rest is
+    /// requested.
+    async fn fetch_bundle(&self, download_url: &str) -> anyhow::Result<String> {
+        let (mut hasher, mut downloaded) = self.resume_state()?;
+        let mut request = self.client.get(download_url);
+
+        if downloaded > 0 {
+            request = request.header(RANGE, format!("bytes={downloaded}-"));
+        }
+
+        let mut response = request.send().await?;
+
+        if downloaded > 0 && response.status() != StatusCode::PARTIAL_CONTENT {
+            info!(
+                status = %response.status(),
+                "Origin does not support resuming downloads. Downloading from scratch."
+            );
+
+            hasher = self.checksum_algorithm.hasher();
+            downloaded = 0;
+
+            if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+                response = self.client.get(download_url).send().await?;
+            }
+        }
+
+        let mut response = response.error_for_status()?;
+        let total = response.content_length().map(|len| len + downloaded);
+
+        self.check_response(
+            download_url,
+            response.headers(),
+            response.content_length(),
+            total,
+        )?;
+
+        let mut output = tokio::fs::File::from_std(self.open_download_part(downloaded)?);
+        let mut state = DownloadState::new(self, download_url, hasher, downloaded, total);
+
+        while let Some(chunk) = response.chunk().await? {
+            state.update(&chunk)?;
+            output
+                .write_all(&chunk)
+                .await
+                .context("Failed to write output tarball file.")?;
+        }
+
+        output
+            .flush()
+            .await
+            .context("Failed to write output tarball file.")?;
+
+        Ok(state.hasher.finalize())
+    }
+
+    /// Blocking counterpart of [`Downloader::fetch_bundle`].
+    #[cfg(feature = "blocking")]
+    fn fetch_bundle_blocking(
+        &self,
+        client: &reqwest::blocking::Client,
+        download_url: &str,
+    ) -> anyhow::Result<String> {
+        let (mut hasher, mut downloaded) = self.resume_state()?;
+        let mut request = client.get(download_url);
+
+        if downloaded > 0 {
+            request = request.header(RANGE, format!("bytes={downloaded}-"));
+        }
+
+        let mut response = request.send()?;
+
+        if downloaded > 0 && response.status() != StatusCode::PARTIAL_CONTENT {
+            info!(
+                status = %response.status(),
+                "Origin does not support resuming downloads. Downloading from scratch."
+            );
+
+            hasher = self.checksum_algorithm.hasher();
+            downloaded = 0;
+
+            if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+                response = client.get(download_url).send()?;
+            }
+        }
+
+        let mut response = response.error_for_status()?;
+        let total = response.content_length().map(|len| len + downloaded);
+
+        self.check_response(
+            download_url,
+            response.headers(),
+            response.content_length(),
+            total,
+        )?;
+
+        let mut output = self.open_download_part(downloaded)?;
+        let mut state = DownloadState::new(self, download_url, hasher, downloaded, total);
+        let mut chunk = vec![0; DOWNLOAD_BUFFER_SIZE];
+
+        loop {
+            let read = match response.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(Self::body_error(err)),
+            };
+
+            state.update(&chunk[..read])?;
+            output
+                .write_all(&chunk[..read])
+                .context("Failed to write output tarball file.")?;
+        }
+
+        output
+            .flush()
+            .context("Failed to write output tarball file.")?;
+
+        Ok(state.hasher.finalize())
+    }
+
+    /// Unwraps the [`reqwest::Error`] `reqwest::blocking` reports body
+    /// errors through, so they're retried like the async ones.
+    #[cfg(feature = "blocking")]
+    fn body_error(err: io::Error) -> anyhow::Error {
+        if !err
+            .get_ref()
+            .is_some_and(|inner| inner.is::<reqwest::Error>())
+        {
+            return err.into();
+        }
+
+        match err
+            .into_inner()
+            .map(|inner| inner.downcast::<reqwest::Error>())
+        {
+            Some(Ok(err)) => (*err).into(),
+            _ => anyhow::anyhow!("Failed to read Tor Expert Bundle from origin."),
+        }
+    }
+
+    /// Hasher and amount of bytes to resume the download from, which are
+    /// those already in the temporary download file when resuming.
+    fn resume_state(&self) -> anyhow::Result<(ChecksumHasher, u64)> {
+        let mut hasher = self.checksum_algorithm.hasher();
+        let downloaded = if self.resume {
+            self.hash_download_part(&mut hasher)?
+        } else {
+            0
+        };
+
+        Ok((hasher, downloaded))
+    }
+
+    /// Rejects responses which are not the tarball, or too large for the
+    /// disk space left.
+    fn check_response(
+        &self,
+        download_url: &str,
+        headers: &HeaderMap,
+        remaining: Option<u64>,
+        total: Option<u64>,
+    ) -> anyhow::Result<()> {
+        let content_type = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+
+        // proxies and CDNs may reply with an error page along a success status
+        if content_type.starts_with("text/html") {
+            anyhow::bail!(
+                "Origin did not return a gzip or xz archive but {content_type} for {download_url}."
+            );
+        }
+
+        if let (Some(remaining), Some(total), Some(available)) =
+            (remaining, total, self.available_space())
+        {
+            let required = remaining + total * UNPACKED_SIZE_RATIO;
+
+            if available < required {
+                return Err(TorError::InsufficientDiskSpace {
+                    required,
+                    available,
+                }
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Opens the temporary file the tarball is downloaded into, appending
+    /// to it when resuming from `downloaded` bytes, or creates it along with
+    /// the download directory if missing.
+    fn open_download_part(&self, downloaded: u64) -> anyhow::Result<File> {
+        if downloaded > 0 {
+            info!(downloaded, "Resuming Tor Expert Bundle download.");
+
+            return OpenOptions::new()
+                .append(true)
+                .open(self.download_part_path())
+                .context("Failed to open output tarball file.");
+        }
+
+        if !self.download_path.exists() {
+            create_dir_all(&self.download_path).context("Failed to create download directory.")?;
+        }
+
+        File::create(self.download_part_path()).context("Failed to create output tarball file.")
+    }
+
+    /// Bytes available to unprivileged users on the filesystem holding the
+    /// download path, if they can be determined.
+    #[cfg(unix)]
+    pub fn available_space(&self) -> Option<u64> {
+        use nix::sys::statvfs::statvfs;
+
+        // the download path may not have been created yet
+        let path = self.download_path.ancestors().find(|path| path.exists())?;
+        let stat = statvfs(path)
+            .inspect_err(
+                |err| debug!(%err, path=%path.display(), "Failed to check available disk space."),
+            )
+            .ok()?;
+
+        // field types vary across platforms
+        #[allow(clippy::unnecessary_cast)]
+        Some(stat.blocks_available() as u64 * stat.fragment_size() as u64)
+    }
+
+    #[cfg(not(unix))]
+    pub fn available_space(&self) -> Option<u64> {
+        None
+    }
+
+    /// Feeds the temporary download file into `hasher`, returning its size
+    /// or `0` if there is nothing to resume.
+    fn hash_download_part(&self, hasher: &mut ChecksumHasher) -> anyhow::Result<u64> {
+        let Ok(mut part) = File::open(self.download_part_path()) else {
+            return Ok(0);
+        };
+
+        io::copy(&mut part, hasher).context("Failed to read partially downloaded tarball.")
+    }
+
+    /// Removes a partially downloaded or unverified tarball.
+    fn remove_download_part(&self) {
+        let _ = remove_file(self.download_part_path());
+    }
+
+    /// Delay before retrying after `attempt` failed attempts, doubling from
+    /// [`RETRY_BASE_DELAY`] up to [`MAX_RETRY_DELAY`].
+    fn retry_delay(attempt: u32) -> Duration {
+        RETRY_BASE_DELAY
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(MAX_RETRY_DELAY)
+    }
+
+    /// Whether `err` is worth retrying: timeouts, failed connections,
+    /// connections reset by the origin and server errors. Anything else,
+    /// e.g. an invalid URL or TLS configuration, would fail again.
+    fn is_transient(err: &anyhow::Error) -> bool {
+        let Some(err) = err.downcast_ref::<reqwest::Error>() else {
+            return false;
+        };
+
+        if let Some(status) = err.status() {
+            return status.is_server_error();
+        }
+
+        let reset = std::iter::successors(std::error::Error::source(err), |err| err.source())
+            .filter_map(|err| err.downcast_ref::<io::Error>())
+            .any(|err| {
+                matches!(
+                    err.kind(),
+                    io::ErrorKind::ConnectionReset
+                        | io::ErrorKind::ConnectionAborted
+                        | io::ErrorKind::BrokenPipe
+                )
+            });
+
+        err.is_timeout() || err.is_connect() || reset
+    }
+
+    /// Path the tarball is cached at, named after the published
+    /// `.tar.gz` bundle whatever compression the mirror actually served;
+    /// it is detected from the content when unpacking.
+    pub fn download_tarball_path(&self) -> PathBuf {
+        self.download_path.join(self.tarball_name())
+    }
+
+    /// Unpacks the tarball, returning the path of every file unpacked.
+    fn decompress_tarball(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let tarball_path = self.download_tarball_path();
+        let mut tarball = File::open(&tarball_path)?;
+        let mut header = Vec::with_capacity(TarballCompression::MAGIC_LEN);
+
+        (&mut tarball)
+            .take(TarballCompression::MAGIC_LEN as u64)
+            .read_to_end(&mut header)?;
+        tarball.rewind()?;
+
+        let tar: Box<dyn Read> = match TarballCompression::detect(&header) {
+            Some(TarballCompression::Gzip) => Box::new(GzDecoder::new(tarball)),
+            Some(TarballCompression::Xz) => Box::new(XzDecoder::new(tarball)),
+            None => anyhow::bail!(
+                "{} is neither a gzip nor an xz archive.",
+                tarball_path.display()
+            ),
+        };
+        let mut archive = Archive::new(tar);
+        let unpack_path = self.unpack_path();
+        let tor_dir_path = self.tor_dir_path();
+
+        // unpacking over a previous bundle would leave its stray files
+        // behind, e.g. libraries which are no longer shipped
+        if tor_dir_path.exists() {
+            debug!(tor_dir_path=%tor_dir_path.display(), "Removing previously unpacked Tor Expert Bundle.");
+            remove_dir_all(&tor_dir_path)
+                .context("Failed to remove previously unpacked Tor Expert Bundle.")?;
+        }
+
+        info!(?unpack_path, "Unpacking tarball.");
+
+        create_dir_all(&unpack_path).context("Failed to create unpack directory.")?;
+
+        let mut unpacked_files = Vec::new();
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.into_owned();
+
+            Self::validate_entry_path(&path)?;
+
+            if let Some(link_name) = entry.link_name()? {
+                Self::validate_entry_path(&link_name)
+                    .with_context(|| format!("Invalid link target for {}.", path.display()))?;
+            }
+
+            entry
+                .unpack_in(&unpack_path)
+                .with_context(|| format!("Failed to unpack {}.", path.display()))?;
+
+            if !entry.header().entry_type().is_dir() {
+                unpacked_files.push(unpack_path.join(path));
+            }
+        }
+
+        Ok(unpacked_files)
+    }
+
+    /// Rejects tarball entries which could be written outside the unpack
+    /// path, i.e. absolute paths or paths with `..` components.
+    fn validate_entry_path(path: &Path) -> anyhow::Result<()> {
+        let escapes = path.components().any(|component| {
+            matches!(
+                component,
+                Component::ParentDir | Component::RootDir | Component::Prefix(_)
+            )
+        });
+
+        if escapes {
+            anyhow::bail!("Tarball contains an unsafe entry: {}.", path.display());
+        }
+
+        Ok(())
+    }
+
+    /// `namespace` within the platform's cache directory, or within the
+    /// directory set through `RUST_TOR_PROJECT_CACHE_DIR` if any.
+    fn default_download_path(namespace: &str) -> anyhow::Result<PathBuf> {
+        let mut download_path = Self::cache_dir_from(std::env::var_os(CACHE_DIR_ENV))?;
+        download_path.push(namespace);
+        Ok(download_path)
+    }
+
+    /// Cache directory given the value of `RUST_TOR_PROJECT_CACHE_DIR`,
+    /// falling back to the platform's one when unset or empty.
+    fn cache_dir_from(env: Option<OsString>) -> anyhow::Result<PathBuf> {
+        match env {
+            Some(cache_dir) if !cache_dir.is_empty() => Ok(PathBuf::from(cache_dir)),
+            _ => Self::platform_cache_dir(),
+        }
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    fn platform_cache_dir() -> anyhow::Result<PathBuf> {
+        dirs::cache_dir().context("No cache directory available on this platform.")
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    fn platform_cache_dir() -> anyhow::Result<PathBuf> {
+        dirs::home_dir().context("No home directory available on this platform.")
+    }
+
+    fn download_url(&self) -> String {
+        Self::bundle_url(&self.base_url, &self.target, &self.version)
+    }
+
+    fn bundle_url(base_url: &str, target: &Target, version: &str) -> String {
+        format!("{base_url}/{version}/tor-expert-bundle-{target}-{version}.tar.gz")
+    }
+
+    fn checksum_url(&self) -> String {
+        format!(
+            "{}.{}",
+            self.download_url(),
+            self.checksum_algorithm.extension()
+        )
+    }
+
+    /// Retrieves the expected digest for the tarball from the checksum file
+    /// published alongside it.
+    async fn fetch_checksum(&self) -> anyhow::Result<String> {
+        let checksum_url = self.checksum_url();
+
+        debug!(%checksum_url, "Fetching Tor Expert Bundle checksum.");
+
+        let checksum_file = self
+            .client
+            .get(&checksum_url)
+            .send()
+            .await
+            .context("Failed to download Tor Expert Bundle checksum from origin.")?
+            .error_for_status()
+            .context("Failed to download Tor Expert Bundle checksum from origin.")?
+            .text()
+            .await
+            .context("Failed to retrieve checksum from response.")?;
+
+        Self::parse_checksum(&checksum_file, self.checksum_algorithm)
+    }
+
+    /// Blocking counterpart of [`Downloader::fetch_checksum`].
+    #[cfg(feature = "blocking")]
+    fn fetch_checksum_blocking(
+        &self,
+        client: &reqwest::blocking::Client,
+    ) -> anyhow::Result<String> {
+        let checksum_url = self.checksum_url();
+
+        debug!(%checksum_url, "Fetching Tor Expert Bundle checksum.");
+
+        let checksum_file = client
+            .get(&checksum_url)
+            .send()
+            .context("Failed to download Tor Expert Bundle checksum from origin.")?
+            .error_for_status()
+            .context("Failed to download Tor Expert Bundle checksum from origin.")?
+            .text()
+            .context("Failed to retrieve checksum from response.")?;
+
+        Self::parse_checksum(&checksum_file, self.checksum_algorithm)
+    }
+
+    /// Parses a `sha256sum` or `sha512sum` formatted file
+    /// (`<digest>  <file name>`) and returns the lowercase hex digest.
+    fn parse_checksum(checksum_file: &str, algorithm: ChecksumAlgorithm) -> anyhow::Result<String> {
+        let digest = checksum_file
+            .split_whitespace()
+            .next()
+            .context("Checksum file is empty.")?;
+
+        if digest.len() != algorithm.hex_len() || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+            anyhow::bail!("Checksum file contains an invalid {algorithm} digest: {digest}");
+        }
+
+        Ok(digest.to_ascii_lowercase())
+    }
+
+    fn verify_checksum(actual: &str, expected: &str) -> Result<()> {
+        if actual != expected {
+            return Err(TorError::Checksum {
+                expected: expected.to_string(),
+                actual: actual.to_string(),
+            });
+        }
+
+        debug!(%actual, "Tor Expert Bundle checksum verified.");
+
+        Ok(())
+    }
+
+    /// Moves the downloaded tarball into place. The tarball is downloaded
+    /// into a temporary file first so an interrupted download never leaves
+    /// a truncated tarball behind.
+    fn store_downloaded_assets(&self) -> anyhow::Result<()> {
+        let download_tarball_path = self.download_tarball_path();
+
+        info!(download_path=?self.download_path, "Storing Tor Artifacts.");
+
+        if download_tarball_path.exists() {
+            debug!(download_tarball_path=%download_tarball_path.display(), "Found output file tarball. Clearing.");
+            remove_file(&download_tarball_path)
+                .context("Failed to delete previous Tor Cached installation.")?;
+        }
+
+        let stored = rename(self.download_part_path(), &download_tarball_path)
+            .context("Failed to move output tarball file into place.");
+
+        if stored.is_err() {
+            self.remove_download_part();
+        }
+
+        stored
+    }
+
+    /// Removes the tarball from the download path, so a corrupt download is
+    /// not picked up as cached on the next run.
+    fn remove_tarball(&self) {
+        let download_tarball_path = self.download_tarball_path();
+        let _ = remove_file(self.verified_marker_path());
+
+        if let Err(err) = remove_file(&download_tarball_path) {
+            warn!(%err, download_tarball_path=%download_tarball_path.display(), "Failed to remove tarball.");
+        }
+    }
+
+    /// Temporary path the tarball is written to while downloading.
+    fn download_part_path(&self) -> PathBuf {
+        let mut download_part_path = self.download_tarball_path().into_os_string();
+        download_part_path.push(".part");
+        PathBuf::from(download_part_path)
+    }
+
+    fn tarball_name(&self) -> String {
+        Self::tarball_file_name(&self.target, &self.version)
+    }
+
+    fn tarball_file_name(target: &Target, version: &str) -> String {
+        format!("tor-expert-bundle-{target}-{version}.tar.gz")
+    }
+
+    async fn fetch_tor_versions(client: &Client, base_url: &str) -> anyhow::Result<Vec<String>> {
+        let response = client
+            .get(format!("{base_url}/"))
+            .send()
+            .await
+            .context("Failed to fetch Tor versions from the archive listing.")?
+            .error_for_status()
+            .context("Tor archive listing responded with an error status.")?;
+
+        let html = response
+            .text()
+            .await
+            .context("Failed to read Tor archive listing.")?;
+
+        Ok(Self::parse_tor_versions(&html))
+    }
+
+    /// Blocking counterpart of [`Downloader::fetch_tor_versions`].
+    #[cfg(feature = "blocking")]
+    fn fetch_tor_versions_blocking(
+        client: &reqwest::blocking::Client,
+        base_url: &str,
+    ) -> anyhow::Result<Vec<String>> {
+        let html = client
+            .get(format!("{base_url}/"))
+            .send()
+            .context("Failed to fetch Tor versions from the archive listing.")?
+            .error_for_status()
+            .context("Tor archive listing responded with an error status.")?
+            .text()
+            .context("Failed to read Tor archive listing.")?;
+
+        Ok(Self::parse_tor_versions(&html))
+    }
+
+    /// Versions linked from the Tor archive listing, in listing order.
+    fn parse_tor_versions(html: &str) -> Vec<String> {
+        let document = Html::parse_document(html);
+
+        let selector = Selector::parse("a").unwrap();
+        document
+            .select(&selector)
+            .filter_map(|el| {
+                let href = el.value().attr("href")?;
+                if href.ends_with('/') && href != "../" {
+                    Some(href.trim_end_matches('/').to_string())
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    async fn resolve_version(
+        client: &Client,
+        base_url: &str,
+        selection: &VersionSelection,
+    ) -> anyhow::Result<String> {
+        match selection {
+            VersionSelection::Version(version) => Ok(version.clone()),
+            VersionSelection::Latest | VersionSelection::Stable => {
+                let versions = Self::fetch_tor_versions(client, base_url).await?;
+
+                Self::select_resolved_version(versions, selection)
+            }
+        }
+    }
+
+    /// Blocking counterpart of [`Downloader::resolve_version`].
+    #[cfg(feature = "blocking")]
+    fn resolve_version_blocking(
+        client: &reqwest::blocking::Client,
+        base_url: &str,
+        selection: &VersionSelection,
+    ) -> anyhow::Result<String> {
+        match selection {
+            VersionSelection::Version(version) => Ok(version.clone()),
+            VersionSelection::Latest | VersionSelection::Stable => {
+                let versions = Self::fetch_tor_versions_blocking(client, base_url)?;
+
+                Self::select_resolved_version(versions, selection)
+            }
+        }
+    }
+
+    /// Picks the version `selection` resolves to from the archive listing.
+    fn select_resolved_version(
+        versions: Vec<String>,
+        selection: &VersionSelection,
+    ) -> anyhow::Result<String> {
+        let stable_only = matches!(selection, VersionSelection::Stable);
+
+        Self::select_version(versions, stable_only)
+            .ok_or_else(|| anyhow::anyhow!("No valid versions found in the Tor archive listing."))
+    }
+
+    /// Name `selection` is stored as in the resolution cache, `None` for
+    /// selections which don't need to be resolved.
+    fn resolution_key(selection: &VersionSelection) -> Option<&'static str> {
+        match selection {
+            VersionSelection::Version(_) => None,
+            VersionSelection::Latest => Some("latest"),
+            VersionSelection::Stable => Some("stable"),
+        }
+    }
+
+    /// Reads the version `selection` resolved to from the resolution cache,
+    /// if it was resolved against `base_url` less than `ttl` ago.
+    fn read_resolution(
+        download_path: &Path,
+        base_url: &str,
+        selection: &VersionSelection,
+        ttl: Duration,
+    ) -> Option<String> {
+        let key = Self::resolution_key(selection)?;
+        let contents = std::fs::read_to_string(download_path.join(RESOLUTION_CACHE_FILE)).ok()?;
+        let cache: serde_json::Value = serde_json::from_str(&contents).ok()?;
+        let entry = cache.get(key)?;
+
+        if entry.get("base_url")?.as_str()? != base_url {
+            return None;
+        }
+
+        let resolved_at = UNIX_EPOCH + Duration::from_secs(entry.get("resolved_at")?.as_u64()?);
+        let age = SystemTime::now().duration_since(resolved_at).ok()?;
+
+        if age > ttl {
+            debug!(?age, ?ttl, "Cached version resolution expired.");
+            return None;
+        }
+
+        let version = entry.get("version")?.as_str()?.to_string();
+
+        debug!(%version, ?age, "Using cached version resolution.");
+
+        Some(version)
+    }
+
+    /// Records the version `selection` resolved to in the resolution cache.
+    /// Failing to do so only means the version is resolved again next time.
+    fn write_resolution(
+        download_path: &Path,
+        base_url: &str,
+        selection: &VersionSelection,
+        version: &str,
+    ) {
+        let Some(key) = Self::resolution_key(selection) else {
+            return;
+        };
+        let cache_path = download_path.join(RESOLUTION_CACHE_FILE);
+        let mut cache = std::fs::read_to_string(&cache_path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .filter(serde_json::Value::is_object)
+            .unwrap_or_else(|| serde_json::json!({}));
+        let resolved_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        cache[key] = serde_json::json!({
+            "base_url": base_url,
+            "version": version,
+            "resolved_at": resolved_at,
+        });
+
+        let written = create_dir_all(download_path)
+            .and_then(|_| std::fs::write(&cache_path, cache.to_string()));
+
+        if let Err(err) = written {
+            warn!(%err, cache_path=%cache_path.display(), "Failed to cache version resolution.");
+        }
+    }
+
+    /// Resolves the version from the tarballs and unpacked bundles present
+    /// in the download path, used when running offline.
+    fn resolve_cached_version(
+        download_path: &Path,
+        bundle_dir_name: &str,
+        target: &Target,
+        selection: &VersionSelection,
+    ) -> anyhow::Result<String> {
+        if let VersionSelection::Version(version) = selection {
+            return Ok(version.clone());
+        }
+
+        let prefix = format!("tor-expert-bundle-{target}-");
+        let versions = std::fs::read_dir(download_path)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| {
+                        let entry = entry.ok()?;
+                        let name = entry.file_name().into_string().ok()?;
+
+                        if entry
+                            .path()
+                            .join(bundle_dir_name)
+                            .join(target.tor_bin_name())
+                            .is_file()
+                        {
+                            return Some(name);
+                        }
+
+                        let version = name.strip_prefix(&prefix)?.strip_suffix(".tar.gz")?;
+
+                        Some(version.to_string())
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let stable_only = matches!(selection, VersionSelection::Stable);
+
+        Self::select_version(versions, stable_only).with_context(|| {
+            format!(
+                "Offline mode is enabled but no Tor Expert Bundle for {target} was found in {}.",
+                download_path.display()
+            )
+        })
+    }
+
+    /// Sorts the archive listing from oldest to newest version, skipping
+    /// entries which are not versions.
+    fn sort_versions(versions: Vec<String>) -> Vec<String> {
+        let mut versions: Vec<TorVersion> =
+            versions.iter().filter_map(|v| v.parse().ok()).collect();
+
+        versions.sort();
+        versions.into_iter().map(|v| v.to_string()).collect()
+    }
+
+    /// Picks the highest version from the archive listing, skipping entries
+    /// which are not versions (e.g. `/icons`). When `stable_only` is set,
+    /// alpha, beta and release candidate versions are ignored.
+    fn select_version(versions: Vec<String>, stable_only: bool) -> Option<String> {
+        versions
+            .iter()
+            .filter_map(|v| v.parse::<TorVersion>().ok())
+            .filter(|v| !stable_only || v.is_stable())
+            .max()
+            .map(|v| v.to_string())
+    }
+}
+
+/// Compression of a Tor Expert Bundle tarball, detected from its first
+/// bytes rather than its name as mirrors may serve either.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TarballCompression {
+    Gzip,
+    Xz,
+}
+
+impl TarballCompression {
+    const ALL: [TarballCompression; 2] = [TarballCompression::Gzip, TarballCompression::Xz];
+
+    /// Length of the longest magic bytes, i.e. how many bytes are needed to
+    /// detect any compression.
+    const MAGIC_LEN: usize = 6;
+
+    /// Bytes every stream of this compression starts with.
+    fn magic(self) -> &'static [u8] {
+        match self {
+            TarballCompression::Gzip => &[0x1f, 0x8b],
+            TarballCompression::Xz => &[0xfd, b'7', b'z', b'X', b'Z', 0x00],
+        }
+    }
+
+    /// Compression of the stream starting with `header`.
+    fn detect(header: &[u8]) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|compression| header.starts_with(compression.magic()))
+    }
+
+    /// Whether `header`, which may be shorter than [`Self::MAGIC_LEN`],
+    /// can be the start of a supported stream.
+    fn is_candidate(header: &[u8]) -> bool {
+        Self::detect(header).is_some()
+            || Self::ALL
+                .into_iter()
+                .any(|compression| compression.magic().starts_with(header))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all, File};
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, Mutex};
+
+    use anyhow::Result;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use sha2::{Digest, Sha256, Sha512};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use crate::{
+        DownloadOptions, Target, TorError, TorVersion, VersionSelection, DEFAULT_VERSION, TOR_BIN,
+    };
+
+    use super::{BundleInfo, ChecksumAlgorithm, Downloader, TarballCompression, DEFAULT_BASE_URL};
+
+    /// Creates an empty directory unique to the test named `name`.
+    fn test_dir(name: &str) -> Result<PathBuf> {
+        let path = std::env::temp_dir().join(format!(
+            "torproject-test-{pid}-{name}",
+            pid = std::process::id()
+        ));
+
+        if path.exists() {
+            remove_dir_all(&path)?;
+        }
+
+        create_dir_all(&path)?;
+
+        Ok(path)
+    }
+
+    /// Writes a tarball resembling the Tor Expert Bundle into `path`.
+    fn write_bundle(path: &Path) -> Result<()> {
+        write_tarball(path, &format!("tor/{TOR_BIN}"), 0o755)
+    }
+
+    /// Writes a tarball with a single file at `entry` into `path`.
+    fn write_tarball(path: &Path, entry: &str, mode: u32) -> Result<()> {
+        let mut archive =
+            tar::Builder::new(GzEncoder::new(File::create(path)?, Compression::default()));
+        let contents = b"#!/bin/sh\n";
+        let mut header = tar::Header::new_gnu();
+
+        header.set_size(contents.len() as u64);
+        header.set_mode(mode);
+        header.set_cksum();
+        archive.append_data(&mut header, entry, &contents[..])?;
+        archive.into_inner()?.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn build_download_url_for_default() -> Result<()> {
+        let downloader = Downloader::new()?;
+        let target = Target::default();
+        let have = downloader.download_url();
+        let want = format!("https://archive.torproject.org/tor-package-archive/torbrowser/{DEFAULT_VERSION}/tor-expert-bundle-{target}-{DEFAULT_VERSION}.tar.gz");
+
+        assert_eq!(want, have);
+
+        Ok(())
+    }
+
+    #[test]
+    fn target_round_trips_through_from_str() -> Result<()> {
+        for target in Target::ALL {
+            assert_eq!(target.to_string().parse::<Target>()?, target);
+        }
+
+        assert_eq!(
+            "x86_64-unknown-linux-gnu".parse::<Target>()?,
+            Target::GnuLinuxX8664
+        );
+        assert_eq!(
+            Target::FreeBSDX8664.to_string().parse::<Target>()?,
+            Target::FreeBSDX8664
+        );
+        assert!("plan9-mips".parse::<Target>().is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn uses_cache_namespace_and_bundle_dir_name() -> Result<()> {
+        let downloader = DownloadOptions::default()
+            .with_cache_namespace("MyApp")
+            .with_bundle_dir_name("tor-bundle")
+            .build()
+            .await?;
+
+        assert!(downloader.download_path().ends_with("MyApp"));
+        assert_eq!(
+            downloader.tor_bin_path(),
+            downloader
+                .download_path()
+                .join(DEFAULT_VERSION)
+                .join("tor-bundle")
+                .join(TOR_BIN)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn honors_cache_dir_env() -> Result<()> {
+        use std::ffi::OsString;
+
+        let cache_dir = PathBuf::from("/var/cache/torproject");
+        let platform_cache_dir = Downloader::platform_cache_dir()?;
+
+        assert_eq!(
+            Downloader::cache_dir_from(Some(cache_dir.clone().into()))?,
+            cache_dir
+        );
+        assert_eq!(
+            Downloader::cache_dir_from(Some(OsString::new()))?,
+            platform_cache_dir
+        );
+        assert_eq!(Downloader::cache_dir_from(None)?, platform_cache_dir);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn builds_download_url_for_mirror() -> Result<()> {
+        let downloader = DownloadOptions::default()
+            .with_base_url("https://mirror.example.org/torbrowser/")
+            .with_target(Target::GnuLinuxX8664)
+            .build()
+            .await?;
+        let want = format!("https://mirror.example.org/torbrowser/{DEFAULT_VERSION}/tor-expert-bundle-linux-x86_64-{DEFAULT_VERSION}.tar.gz");
+
+        assert_eq!(downloader.download_url(), want);
+        assert_eq!(downloader.checksum_url(), format!("{want}.sha256sum"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn builds_download_url_for_custom_target() -> Result<()> {
+        let downloader = DownloadOptions::default()
+            .with_target_str("linux-riscv64")
+            .build()
+            .await?;
+        let want = format!("https://archive.torproject.org/tor-package-archive/torbrowser/{DEFAULT_VERSION}/tor-expert-bundle-linux-riscv64-{DEFAULT_VERSION}.tar.gz");
+
+        assert_eq!(downloader.download_url(), want);
+
+        let downloader = DownloadOptions::default()
+            .with_target_str("linux-x86_64")
+            .build()
+            .await?;
+
+        assert_eq!(downloader.target, Target::GnuLinuxX8664);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parses_sha256sum_file() -> Result<()> {
+        let digest = "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855";
+        let have = Downloader::parse_checksum(
+            &format!("{digest}  tor-expert-bundle.tar.gz\n"),
+            ChecksumAlgorithm::Sha256,
+        )?;
+
+        assert_eq!(have, digest.to_ascii_lowercase());
+        assert!(Downloader::parse_checksum("", ChecksumAlgorithm::Sha256).is_err());
+        assert!(
+            Downloader::parse_checksum("not-a-digest  file", ChecksumAlgorithm::Sha256).is_err()
+        );
+        assert!(Downloader::parse_checksum(
+            &format!("{digest}  tor-expert-bundle.tar.gz\n"),
+            ChecksumAlgorithm::Sha512
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn caps_retry_delay() {
+        use std::time::Duration;
+
+        assert_eq!(Downloader::retry_delay(0), Duration::from_millis(500));
+        assert_eq!(Downloader::retry_delay(3), Duration::from_secs(4));
+        assert_eq!(Downloader::retry_delay(40), super::MAX_RETRY_DELAY);
+        assert_eq!(Downloader::retry_delay(u32::MAX), super::MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn parses_sha512sum_file() -> Result<()> {
+        let digest = format!("{:x}", Sha512::digest(b""));
+        let have = Downloader::parse_checksum(
+            &format!(
+                "{}  tor-expert-bundle.tar.gz\n",
+                digest.to_ascii_uppercase()
+            ),
+            ChecksumAlgorithm::Sha512,
+        )?;
+
+        assert_eq!(have, digest);
+        assert_eq!(
+            Downloader::file_digest(Path::new("/dev/null"), ChecksumAlgorithm::Sha512)?,
+            digest
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn verifies_checksum() {
+        let empty = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        let other = "0000000000000000000000000000000000000000000000000000000000000000";
+
+        assert!(Downloader::verify_checksum(empty, empty).is_ok());
+
+        let err = Downloader::verify_checksum(empty, other).unwrap_err();
+        assert!(matches!(err, TorError::Checksum { .. }));
+
+        let err = err.to_string();
+        assert!(err.contains(other));
+        assert!(err.contains(empty));
+    }
 
-        let selector = Selector::parse("a").unwrap();
-        let versions: Vec<String> = document
-            .select(&selector)
-            .filter_map(|el| {
-                let href = el.value().attr("href")?;
-                if href.ends_with('/') && href != "../" {
-                    Some(href.trim_end_matches('/').to_string())
-                } else {
-                    None
-                }
-            })
-            .collect();
+    #[test]
+    fn selects_latest_and_stable_versions() {
+        let listing = ["13.5.9", "14.0", "14.0.4", "14.5a3", "icons", "14.5a1"]
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<String>>();
+
+        assert_eq!(
+            Downloader::select_version(listing.clone(), false).as_deref(),
+            Some("14.5a3")
+        );
+        assert_eq!(
+            Downloader::select_version(listing, true).as_deref(),
+            Some("14.0.4")
+        );
+        assert_eq!(
+            Downloader::select_version(vec!["icons".into()], false),
+            None
+        );
+    }
 
-        Ok(versions)
+    #[test]
+    fn sorts_versions() {
+        let listing = ["14.0.4", "icons", "13.5.9", "14.5a1", "14.0", "14.0.10"]
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<String>>();
+
+        assert_eq!(
+            Downloader::sort_versions(listing),
+            vec!["13.5.9", "14.0", "14.0.4", "14.0.10", "14.5a1"]
+        );
     }
 
-    async fn resolve_version(selection: &VersionSelection) -> Result<String> {
-        match selection {
-            VersionSelection::Version(version) => Ok(version.clone()),
-            VersionSelection::Latest | VersionSelection::Stable => {
-                let versions = Self::fetch_tor_versions().await?;
-                versions
-                    .into_iter()
-                    .filter(|v| {
-                        if matches!(selection, VersionSelection::Stable) {
-                            !v.contains("alpha") && !v.contains("beta") && !v.contains("rc")
-                        } else {
-                            true
-                        }
-                    })
-                    .max_by(|a, b| {
-                        let ver_a = semver::Version::parse(a)
-                            .unwrap_or_else(|_| semver::Version::new(0, 0, 0));
-                        let ver_b = semver::Version::parse(b)
-                            .unwrap_or_else(|_| semver::Version::new(0, 0, 0));
-                        ver_a.cmp(&ver_b)
-                    })
-                    .ok_or_else(|| anyhow::anyhow!("No valid versions found"))
-            }
+    #[tokio::test]
+    async fn uses_cached_tarball() -> Result<()> {
+        let download_path = test_dir("uses_cached_tarball")?;
+        let downloader = DownloadOptions::default()
+            .with_download_path(download_path.clone())
+            .with_checksum_verification(false)
+            .build()
+            .await?;
+
+        write_bundle(&downloader.download_tarball_path())?;
+
+        let unpacked_files = downloader.download().await?;
+
+        assert!(downloader.tor_bin_path().exists());
+        assert_eq!(unpacked_files, vec![downloader.tor_bin_path()]);
+
+        remove_dir_all(download_path)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn downloads_blocking() -> Result<()> {
+        let download_path = test_dir("downloads_blocking")?;
+        let downloader = DownloadOptions::default()
+            .with_download_path(download_path.clone())
+            .with_checksum_verification(false)
+            .build_blocking()?;
+
+        write_bundle(&downloader.download_tarball_path())?;
+        downloader.download_blocking()?;
+
+        assert!(downloader.tor_bin_path().exists());
+
+        remove_dir_all(download_path)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn downloads_blocking_from_origin() -> Result<()> {
+        // only serves the mock origin, requests are made without it
+        let runtime = tokio::runtime::Runtime::new()?;
+
+        for ranges in [true, false] {
+            let download_path = test_dir(&format!("downloads_blocking_from_origin_{ranges}"))?;
+            let bundle_path = download_path.join("bundle.tar.gz");
+
+            write_bundle(&bundle_path)?;
+
+            let bundle = std::fs::read(&bundle_path)?;
+            let (base_url, requested) = runtime.block_on(serve_bundle(bundle.clone(), ranges))?;
+            let downloaded = Arc::new(Mutex::new(0));
+            let progress = Arc::clone(&downloaded);
+            let downloader = DownloadOptions::default()
+                .with_download_path(download_path.clone())
+                .with_base_url(&base_url)
+                .with_resume(true)
+                .with_progress(move |bytes, _| *progress.lock().unwrap() = bytes)
+                .build_blocking()?;
+
+            std::fs::write(downloader.download_part_path(), &bundle[..bundle.len() / 2])?;
+            downloader.download_blocking()?;
+
+            assert_eq!(
+                *requested.lock().unwrap(),
+                vec![Some((bundle.len() / 2).to_string())]
+            );
+            assert_eq!(*downloaded.lock().unwrap(), bundle.len() as u64);
+            assert_eq!(std::fs::read(downloader.download_tarball_path())?, bundle);
+            assert!(downloader.tor_bin_path().is_file());
+
+            remove_dir_all(download_path)?;
         }
+
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use anyhow::Result;
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn resolves_version_blocking() -> Result<()> {
+        let runtime = tokio::runtime::Runtime::new()?;
+        let base_url = runtime.block_on(serve_archive(
+            &["14.5a1", "13.5.9", "14.0.4", "icons"],
+            &[],
+            Vec::new(),
+        ))?;
+        let download_path = test_dir("resolves_version_blocking")?;
+
+        for (selection, version) in [
+            (VersionSelection::Latest, "14.5a1"),
+            (VersionSelection::Stable, "14.0.4"),
+        ] {
+            let downloader = DownloadOptions::default()
+                .with_download_path(download_path.clone())
+                .with_base_url(&base_url)
+                .with_version_selection(selection)
+                .build_blocking()?;
+
+            assert_eq!(downloader.version(), version);
+        }
 
-    use crate::{DownloadOptions, Target, VersionSelection, DEFAULT_VERSION};
+        remove_dir_all(download_path)?;
+        Ok(())
+    }
 
-    use super::Downloader;
+    #[tokio::test]
+    async fn downloads_bundle_for_other_target() -> Result<()> {
+        let download_path = test_dir("downloads_bundle_for_other_target")?;
+        let target = Target::ALL
+            .into_iter()
+            .find(|target| target.tor_bin_name() != TOR_BIN)
+            .unwrap();
+        let downloader = DownloadOptions::default()
+            .with_download_path(download_path.clone())
+            .with_target(target.clone())
+            .with_checksum_verification(false)
+            .build()
+            .await?;
+
+        write_tarball(
+            &downloader.download_tarball_path(),
+            &format!("tor/{}", target.tor_bin_name()),
+            0o755,
+        )?;
+        downloader.download().await?;
+
+        assert!(!target.is_host());
+        assert!(downloader.tor_bin_path().is_file());
+        assert!(matches!(
+            crate::Tor::from_downloader(&downloader),
+            Err(TorError::Spawn(_))
+        ));
+
+        remove_dir_all(download_path)?;
+        Ok(())
+    }
 
     #[test]
-    fn build_download_url_for_default() -> Result<()> {
-        let downloader = Downloader::new()?;
-        let target = Target::default();
-        let have = downloader.download_url();
-        let want = format!("https://archive.torproject.org/tor-package-archive/torbrowser/{DEFAULT_VERSION}/tor-expert-bundle-{target}-{DEFAULT_VERSION}.tar.gz");
+    fn detects_runtime_target() {
+        let target = Target::detect_runtime();
 
-        assert_eq!(want, have);
+        assert!(target.is_host());
+        #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+        assert_eq!(target, Target::default());
+    }
+
+    #[tokio::test]
+    async fn clears_cache() -> Result<()> {
+        let download_path = test_dir("clears_cache")?;
+        let downloader = |version: &str| {
+            DownloadOptions::default()
+                .with_download_path(download_path.clone())
+                .with_version_selection(VersionSelection::Version(version.to_string()))
+                .with_offline(true)
+                .build()
+        };
+        let current = downloader("14.0.1").await?;
+        let other = downloader("13.5.1").await?;
+
+        for downloader in [&current, &other] {
+            write_bundle(&downloader.download_tarball_path())?;
+            downloader.download().await?;
+        }
+
+        let notes = download_path.join("notes.txt");
+        let user_dir = download_path.join("14.0.2");
+
+        std::fs::write(&notes, "keep me")?;
+        create_dir_all(&user_dir)?;
+
+        current.clear_cache()?;
+
+        assert!(!current.download_tarball_path().exists());
+        assert!(!current.unpack_path().exists());
+        assert!(other.tor_bin_path().exists());
+
+        // clearing again is a no-op
+        current.clear_cache()?;
+        current.clear_all()?;
+
+        assert!(!other.download_tarball_path().exists());
+        assert!(!other.unpack_path().exists());
+        assert!(notes.is_file());
+        assert!(user_dir.is_dir());
+
+        remove_dir_all(download_path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn uses_cache_when_offline() -> Result<()> {
+        let download_path = test_dir("uses_cache_when_offline")?;
+        let missing = DownloadOptions::default()
+            .with_download_path(download_path.clone())
+            .with_version_selection(VersionSelection::Version("13.5.1".to_string()))
+            .with_offline(true)
+            .build()
+            .await?;
+        let err = missing.download().await.unwrap_err();
+
+        assert!(err
+            .to_string()
+            .contains(&missing.download_tarball_path().display().to_string()));
+
+        write_bundle(&download_path.join(format!(
+            "tor-expert-bundle-{}-14.0.1.tar.gz",
+            Target::default()
+        )))?;
+        write_bundle(&download_path.join(format!(
+            "tor-expert-bundle-{}-14.5a1.tar.gz",
+            Target::default()
+        )))?;
+
+        let downloader = DownloadOptions::default()
+            .with_download_path(download_path.clone())
+            .with_version_selection(VersionSelection::Stable)
+            .with_offline(true)
+            .build()
+            .await?;
+
+        assert_eq!(downloader.version(), "14.0.1");
+
+        downloader.download().await?;
+
+        assert!(downloader.tor_bin_path().exists());
+
+        // the bundle unpacked before is used once the tarball is gone
+        std::fs::remove_file(downloader.download_tarball_path())?;
+
+        assert_eq!(
+            downloader.download().await?,
+            vec![downloader.tor_bin_path()]
+        );
+
+        remove_dir_all(download_path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reuses_resolved_version_within_ttl() -> Result<()> {
+        let download_path = test_dir("reuses_resolved_version_within_ttl")?;
+        let ttl = std::time::Duration::from_secs(60);
+
+        Downloader::write_resolution(
+            &download_path,
+            "https://mirror.example.org",
+            &VersionSelection::Latest,
+            "14.5a1",
+        );
+
+        let downloader = DownloadOptions::default()
+            .with_download_path(download_path.clone())
+            .with_base_url("https://mirror.example.org")
+            .with_version_selection(VersionSelection::Latest)
+            .with_resolution_ttl(ttl)
+            .build()
+            .await?;
+
+        assert_eq!(downloader.version(), "14.5a1");
+        assert_eq!(
+            Downloader::read_resolution(
+                &download_path,
+                "https://mirror.example.org",
+                &VersionSelection::Stable,
+                ttl
+            ),
+            None
+        );
+        assert_eq!(
+            Downloader::read_resolution(
+                &download_path,
+                "https://archive.torproject.org",
+                &VersionSelection::Latest,
+                ttl
+            ),
+            None
+        );
+
+        remove_dir_all(download_path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn plans_download() -> Result<()> {
+        let download_path = test_dir("plans_download")?;
+        let options = DownloadOptions::default()
+            .with_download_path(download_path.clone())
+            .with_base_url("https://mirror.example.org")
+            .with_target(Target::GnuLinuxX8664);
+        let plan = options.plan()?;
+
+        assert_eq!(plan.version, DEFAULT_VERSION);
+        assert_eq!(
+            plan.download_url,
+            format!(
+                "https://mirror.example.org/{DEFAULT_VERSION}/tor-expert-bundle-linux-x86_64-{DEFAULT_VERSION}.tar.gz"
+            )
+        );
+        assert_eq!(plan, options.build().await?.plan());
+        assert!(!plan.download_tarball_path.exists());
+
+        let options = DownloadOptions::default()
+            .with_download_path(download_path.clone())
+            .with_version_selection(VersionSelection::Stable);
+
+        assert!(matches!(options.plan(), Err(TorError::Download(_))));
+
+        Downloader::write_resolution(
+            &download_path,
+            DEFAULT_BASE_URL,
+            &VersionSelection::Stable,
+            "14.0.4",
+        );
+
+        let plan = options
+            .with_resolution_ttl(std::time::Duration::from_secs(60))
+            .plan()?;
+
+        assert_eq!(plan.version, "14.0.4");
+        assert_eq!(
+            plan.download_tarball_path,
+            download_path.join(format!(
+                "tor-expert-bundle-{}-14.0.4.tar.gz",
+                Target::default()
+            ))
+        );
+
+        remove_dir_all(download_path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stores_assets_in_nested_download_path() -> Result<()> {
+        let root = test_dir("stores_assets_in_nested_download_path")?;
+        let download_path = root.join("foo").join("bar");
+        let downloader = DownloadOptions::default()
+            .with_download_path(download_path.clone())
+            .build()
+            .await?;
+
+        downloader.open_download_part(0)?;
+        downloader.store_downloaded_assets()?;
+
+        assert!(downloader.download_tarball_path().exists());
+        assert!(!downloader.download_part_path().exists());
+
+        remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn removes_corrupt_tarball() -> Result<()> {
+        let download_path = test_dir("removes_corrupt_tarball")?;
+        let downloader = DownloadOptions::default()
+            .with_download_path(download_path.clone())
+            .with_checksum_verification(false)
+            .build()
+            .await?;
+
+        std::fs::write(downloader.download_tarball_path(), b"not a tarball")?;
+
+        let err = downloader.download().await.unwrap_err();
+
+        assert!(matches!(err, TorError::Unpack(_)));
+        assert!(!downloader.download_tarball_path().exists());
+
+        remove_dir_all(download_path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_path_traversal_entries() -> Result<()> {
+        let download_path = test_dir("rejects_path_traversal_entries")?;
+        let downloader = DownloadOptions::default()
+            .with_download_path(download_path.clone())
+            .with_checksum_verification(false)
+            .build()
+            .await?;
+        let mut archive = tar::Builder::new(GzEncoder::new(
+            File::create(downloader.download_tarball_path())?,
+            Compression::default(),
+        ));
+        let contents = b"#!/bin/sh\n";
+        let mut header = tar::Header::new_gnu();
+
+        // the builder refuses `..` components, so the name is set verbatim
+        header.as_old_mut().name[..10].copy_from_slice(b"../escaped");
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+        archive.append(&header, &contents[..])?;
+        archive.into_inner()?.finish()?;
+
+        let err = downloader.download().await.unwrap_err();
+
+        assert!(matches!(err, TorError::Unpack(_)));
+        assert!(err.to_string().contains("unsafe entry"));
+        assert!(!download_path.join("escaped").exists());
+        assert!(!downloader.download_tarball_path().exists());
+
+        remove_dir_all(download_path)?;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn marks_tor_binary_as_executable() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let download_path = test_dir("marks_tor_binary_as_executable")?;
+        let downloader = DownloadOptions::default()
+            .with_download_path(download_path.clone())
+            .with_checksum_verification(false)
+            .build()
+            .await?;
+
+        write_tarball(&downloader.download_tarball_path(), "tor/tor", 0o644)?;
+        downloader.download().await?;
+
+        let mode = downloader.tor_bin_path().metadata()?.permissions().mode();
+        assert_eq!(mode & 0o111, 0o111);
+
+        remove_dir_all(download_path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn removes_previously_unpacked_bundle() -> Result<()> {
+        let download_path = test_dir("removes_previously_unpacked_bundle")?;
+        let downloader = DownloadOptions::default()
+            .with_download_path(download_path.clone())
+            .with_checksum_verification(false)
+            .build()
+            .await?;
+        let stray_path = downloader.unpack_path().join("tor").join("libstray.so");
+
+        create_dir_all(downloader.unpack_path().join("tor"))?;
+        File::create(&stray_path)?;
+        write_bundle(&downloader.download_tarball_path())?;
+        downloader.download().await?;
+
+        assert!(downloader.tor_bin_path().exists());
+        assert!(!stray_path.exists());
+
+        remove_dir_all(download_path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn keeps_versions_side_by_side() -> Result<()> {
+        let download_path = test_dir("keeps_versions_side_by_side")?;
+        let legacy_path = download_path.join("tor");
+
+        create_dir_all(&legacy_path)?;
+        File::create(legacy_path.join(TOR_BIN))?;
+
+        let mut tor_bin_paths = Vec::new();
+
+        for version in ["14.0.3", "14.0.4"] {
+            let downloader = DownloadOptions::default()
+                .with_download_path(download_path.clone())
+                .with_version_selection(VersionSelection::Version(version.to_string()))
+                .with_checksum_verification(false)
+                .build()
+                .await?;
+
+            write_bundle(&downloader.download_tarball_path())?;
+            downloader.download().await?;
+
+            assert_eq!(
+                downloader.tor_bin_path(),
+                download_path.join(version).join("tor").join(TOR_BIN)
+            );
+            tor_bin_paths.push(downloader.tor_bin_path());
+        }
+
+        assert!(tor_bin_paths.iter().all(|path| path.exists()));
+        assert!(!legacy_path.exists());
+
+        remove_dir_all(download_path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_bundle_without_tor_binary() -> Result<()> {
+        let download_path = test_dir("rejects_bundle_without_tor_binary")?;
+        let downloader = DownloadOptions::default()
+            .with_download_path(download_path.clone())
+            .with_checksum_verification(false)
+            .build()
+            .await?;
+
+        write_tarball(&downloader.download_tarball_path(), "data/geoip", 0o644)?;
 
+        let err = downloader.download().await.unwrap_err();
+
+        assert!(matches!(err, TorError::Unpack(_)));
+        assert!(err.to_string().contains("did not contain a Tor binary"));
+
+        remove_dir_all(download_path)?;
         Ok(())
     }
 
@@ -354,12 +3280,12 @@ mod tests {
         let downloader = Downloader::new_with_options(options).await?;
         let version = downloader.version();
 
-        // Version should be parseable as semver
-        assert!(semver::Version::parse(version).is_ok());
+        // Version should be parseable using the archive version scheme
+        assert!(version.parse::<TorVersion>().is_ok());
 
         // Latest version should be >= default version
-        let latest_ver = semver::Version::parse(version).unwrap();
-        let default_ver = semver::Version::parse(DEFAULT_VERSION).unwrap();
+        let latest_ver = version.parse::<TorVersion>()?;
+        let default_ver = DEFAULT_VERSION.parse::<TorVersion>()?;
         assert!(latest_ver >= default_ver);
 
         Ok(())
@@ -372,8 +3298,8 @@ mod tests {
         let downloader = Downloader::new_with_options(options).await?;
         let version = downloader.version();
 
-        // Version should be parseable as semver
-        assert!(semver::Version::parse(version).is_ok());
+        // Version should be parseable using the archive version scheme
+        assert!(version.parse::<TorVersion>()?.is_stable());
 
         // Should not contain alpha/beta/rc
         assert!(!version.contains("alpha"));
@@ -383,6 +3309,586 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn times_out_stuck_downloads() -> Result<()> {
+        let download_path = test_dir("times_out_stuck_downloads")?;
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let downloader = DownloadOptions::default()
+            .with_download_path(download_path.clone())
+            .with_base_url(&format!("http://{}", listener.local_addr()?))
+            .with_timeout(std::time::Duration::from_millis(200))
+            .with_retries(0)
+            .build()
+            .await?;
+        let err = downloader.download().await.unwrap_err();
+
+        assert!(matches!(err, TorError::Download(_)));
+        assert!(!downloader.download_tarball_path().exists());
+
+        remove_dir_all(download_path)?;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn fails_early_without_disk_space() -> Result<()> {
+        let download_path = test_dir("fails_early_without_disk_space")?;
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let base_url = format!("http://{}", listener.local_addr()?);
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0; 1024];
+                let _ = stream.read(&mut buf).await;
+                let _ = stream
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 1000000000000000\r\n\r\n")
+                    .await;
+            }
+        });
+
+        let downloader = DownloadOptions::default()
+            .with_download_path(download_path.clone())
+            .with_base_url(&base_url)
+            .build()
+            .await?;
+
+        assert!(downloader.available_space().is_some());
+
+        let err = downloader.download().await.unwrap_err();
+
+        assert!(
+            matches!(err, TorError::InsufficientDiskSpace { required, available } if required > available)
+        );
+        assert!(!downloader.download_part_path().exists());
+
+        remove_dir_all(download_path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_html_responses() -> Result<()> {
+        let download_path = test_dir("rejects_html_responses")?;
+        let page = b"<html><body>Service Unavailable</body></html>".to_vec();
+        let (base_url, _) = serve_bundle(page.clone(), false).await?;
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let html_base_url = format!("http://{}", listener.local_addr()?);
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0; 1024];
+                let _ = stream.read(&mut buf).await;
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    page.len()
+                );
+                let _ = stream.write_all(head.as_bytes()).await;
+                let _ = stream.write_all(&page).await;
+            }
+        });
+
+        for base_url in [base_url, html_base_url] {
+            let downloader = DownloadOptions::default()
+                .with_download_path(download_path.clone())
+                .with_base_url(&base_url)
+                .with_checksum_verification(false)
+                .build()
+                .await?;
+            let err = downloader.download().await.unwrap_err();
+
+            assert!(matches!(err, TorError::Download(_)));
+            assert!(
+                err.to_string()
+                    .contains("did not return a gzip or xz archive"),
+                "{err}"
+            );
+            assert!(!downloader.download_part_path().exists());
+            assert!(!downloader.download_tarball_path().exists());
+        }
+
+        remove_dir_all(download_path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unpacks_xz_tarballs() -> Result<()> {
+        let download_path = test_dir("unpacks_xz_tarballs")?;
+        let mut archive = tar::Builder::new(xz2::write::XzEncoder::new(Vec::new(), 6));
+        let contents = b"#!/bin/sh\n";
+        let mut header = tar::Header::new_gnu();
+
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+        archive.append_data(&mut header, format!("tor/{TOR_BIN}"), &contents[..])?;
+
+        let bundle = archive.into_inner()?.finish()?;
+        let (base_url, _) = serve_bundle(bundle, false).await?;
+        let downloader = DownloadOptions::default()
+            .with_download_path(download_path.clone())
+            .with_base_url(&base_url)
+            .with_checksum_verification(false)
+            .build()
+            .await?;
+
+        downloader.download().await?;
+
+        assert_eq!(std::fs::read(downloader.tor_bin_path())?, contents);
+
+        // gzip tarballs are still unpacked from the cache
+        write_bundle(&downloader.download_tarball_path())?;
+        downloader.unpack().map_err(anyhow::Error::new)?;
+
+        assert!(downloader.tor_bin_path().is_file());
+        assert_eq!(
+            TarballCompression::detect(&[0x1f, 0x8b, 0x08]),
+            Some(TarballCompression::Gzip)
+        );
+        assert!(TarballCompression::is_candidate(&[0xfd, b'7']));
+        assert!(!TarballCompression::is_candidate(b"<html>"));
+
+        remove_dir_all(download_path)?;
+        Ok(())
+    }
+
+    /// Serves `bundle` and its checksum over HTTP, honouring `Range`
+    /// requests if `ranges` is set. Returns the base URL and the `Range`
+    /// header of every bundle request.
+    async fn serve_bundle(
+        bundle: Vec<u8>,
+        ranges: bool,
+    ) -> Result<(String, Arc<Mutex<Vec<Option<String>>>>)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let base_url = format!("http://{}", listener.local_addr()?);
+        let requested = Arc::new(Mutex::new(Vec::new()));
+        let checksum = format!("{:x}  bundle\n", Sha256::digest(&bundle));
+        let log = Arc::clone(&requested);
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buf).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+
+                let request = String::from_utf8_lossy(&request).to_lowercase();
+                let (status, body) = if request.contains(".sha256sum ") {
+                    ("200 OK", checksum.as_bytes().to_vec())
+                } else {
+                    let range = request
+                        .lines()
+                        .find_map(|line| line.strip_prefix("range: bytes="))
+                        .map(|range| range.trim_end_matches('-').to_string());
+
+                    log.lock().unwrap().push(range.clone());
+
+                    match range.and_then(|range| range.parse::<usize>().ok()) {
+                        Some(start) if ranges => ("206 Partial Content", bundle[start..].to_vec()),
+                        _ => ("200 OK", bundle.clone()),
+                    }
+                };
+                let head = format!(
+                    "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+
+                let _ = stream.write_all(head.as_bytes()).await;
+                let _ = stream.write_all(&body).await;
+            }
+        });
+
+        Ok((base_url, requested))
+    }
+
+    /// Serves a mock of the Tor archive listing `listed` versions, with
+    /// `bundle` and its checksum published for the host target of the
+    /// `bundled` versions only. Returns the base URL.
+    async fn serve_archive(listed: &[&str], bundled: &[&str], bundle: Vec<u8>) -> Result<String> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let base_url = format!("http://{}", listener.local_addr()?);
+        let checksum = format!("{:x}  bundle\n", Sha256::digest(&bundle));
+        let checksum512 = format!("{:x}  bundle\n", Sha512::digest(&bundle));
+        let links: String = ["../"]
+            .into_iter()
+            .chain(listed.iter().copied())
+            .map(|version| {
+                format!(
+                    "<a href=\"{}/\">{version}</a>\n",
+                    version.trim_end_matches('/')
+                )
+            })
+            .chain(["<a href=\"README\">README</a>\n".to_string()])
+            .collect();
+        let listing = format!("<html><body><pre>{links}</pre></body></html>");
+        let bundles: Vec<String> = bundled
+            .iter()
+            .map(|version| {
+                format!(
+                    "/{version}/tor-expert-bundle-{}-{version}.tar.gz",
+                    Target::default()
+                )
+            })
+            .collect();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buf).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+
+                let request = String::from_utf8_lossy(&request);
+                let mut request_line = request.split_whitespace();
+                let method = request_line.next().unwrap_or_default();
+                let path = request_line.next().unwrap_or_default();
+                let (status, body) = match path {
+                    "/" => ("200 OK", listing.as_bytes().to_vec()),
+                    path if bundles.iter().any(|bundle| path == bundle) => {
+                        ("200 OK", bundle.clone())
+                    }
+                    path if path
+                        .strip_suffix(".sha256sum")
+                        .is_some_and(|path| bundles.iter().any(|bundle| path == bundle)) =>
+                    {
+                        ("200 OK", checksum.as_bytes().to_vec())
+                    }
+                    path if path
+                        .strip_suffix(".sha512sum")
+                        .is_some_and(|path| bundles.iter().any(|bundle| path == bundle)) =>
+                    {
+                        ("200 OK", checksum512.as_bytes().to_vec())
+                    }
+                    _ => ("404 Not Found", Vec::new()),
+                };
+                let head = format!(
+                    "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+
+                let _ = stream.write_all(head.as_bytes()).await;
+
+                if method != "HEAD" {
+                    let _ = stream.write_all(&body).await;
+                }
+            }
+        });
+
+        Ok(base_url)
+    }
+
+    #[tokio::test]
+    async fn downloads_from_mock_archive() -> Result<()> {
+        let download_path = test_dir("downloads_from_mock_archive")?;
+        let bundle_path = download_path.join("bundle.tar.gz");
+
+        write_bundle(&bundle_path)?;
+
+        let versions = ["13.5.9", "14.0.4", "14.5a1"];
+        let base_url = serve_archive(&versions, &versions, std::fs::read(&bundle_path)?).await?;
+
+        for (selection, version) in [
+            (VersionSelection::Latest, "14.5a1"),
+            (VersionSelection::Stable, "14.0.4"),
+        ] {
+            let downloader = DownloadOptions::default()
+                .with_download_path(download_path.clone())
+                .with_base_url(&base_url)
+                .with_version_selection(selection)
+                .build()
+                .await?;
+
+            assert_eq!(downloader.version(), version);
+
+            let files = downloader.download().await?;
+
+            assert!(files.contains(&downloader.tor_bin_path()));
+            assert!(downloader.download_tarball_path().is_file());
+        }
+
+        let missing = DownloadOptions::default()
+            .with_download_path(download_path.clone())
+            .with_base_url(&base_url)
+            .with_version_selection(VersionSelection::Version("12.0".to_string()))
+            .build()
+            .await?;
+
+        assert!(matches!(
+            missing.download().await,
+            Err(TorError::Download(_))
+        ));
+
+        remove_dir_all(download_path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn verifies_sha512_checksum() -> Result<()> {
+        let download_path = test_dir("verifies_sha512_checksum")?;
+        let bundle_path = download_path.join("bundle.tar.gz");
+
+        write_bundle(&bundle_path)?;
+
+        let base_url = serve_archive(&[], &[DEFAULT_VERSION], std::fs::read(&bundle_path)?).await?;
+        let downloader = DownloadOptions::default()
+            .with_download_path(download_path.clone())
+            .with_base_url(&base_url)
+            .with_checksum_algorithm(ChecksumAlgorithm::Sha512)
+            .build()
+            .await?;
+
+        assert!(downloader.checksum_url().ends_with(".tar.gz.sha512sum"));
+
+        downloader.download().await?;
+
+        assert!(downloader.tor_bin_path().is_file());
+
+        // the SHA-512 verified marker doesn't apply to SHA-256 checks
+        let sha256 = DownloadOptions::default()
+            .with_download_path(download_path.clone())
+            .with_base_url(&base_url)
+            .build()
+            .await?;
+
+        assert!(sha256.read_verified_marker().is_none());
+        assert!(downloader.read_verified_marker().is_some());
+        assert!(sha256.is_cached().await?);
+
+        remove_dir_all(download_path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn lists_versions_from_mock_archive() -> Result<()> {
+        let base_url = serve_archive(
+            &["14.5a1", "13.5.9", "14.0.4", "icons"],
+            &["13.5.9", "14.5a1"],
+            Vec::new(),
+        )
+        .await?;
+        let downloader = DownloadOptions::default()
+            .with_download_path(test_dir("lists_versions_from_mock_archive")?)
+            .with_base_url(&base_url)
+            .build()
+            .await?;
+
+        assert_eq!(
+            downloader
+                .list_available_versions(&Target::default())
+                .await?,
+            vec!["13.5.9", "14.5a1"]
+        );
+
+        remove_dir_all(downloader.download_path())?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn lists_bundles_with_sizes() -> Result<()> {
+        let base_url = serve_archive(
+            &["14.5a1", "13.5.9", "14.0.4", "icons"],
+            &["13.5.9", "14.5a1"],
+            vec![0; 42],
+        )
+        .await?;
+        let downloader = DownloadOptions::default()
+            .with_download_path(test_dir("lists_bundles_with_sizes")?)
+            .with_base_url(&base_url)
+            .build()
+            .await?;
+        let bundle = |version: &str, stable, available, size| BundleInfo {
+            version: version.to_string(),
+            stable,
+            available,
+            size,
+        };
+
+        assert_eq!(
+            downloader.available_bundles(&Target::default()).await?,
+            vec![
+                bundle("13.5.9", true, true, Some(42)),
+                bundle("14.0.4", true, false, None),
+                bundle("14.5a1", false, true, Some(42)),
+            ]
+        );
+
+        remove_dir_all(downloader.download_path())?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn resumes_interrupted_download() -> Result<()> {
+        for ranges in [true, false] {
+            let download_path = test_dir(&format!("resumes_interrupted_download_{ranges}"))?;
+            let bundle_path = download_path.join("bundle.tar.gz");
+
+            write_bundle(&bundle_path)?;
+
+            let bundle = std::fs::read(&bundle_path)?;
+            let (base_url, requested) = serve_bundle(bundle.clone(), ranges).await?;
+            let downloader = DownloadOptions::default()
+                .with_download_path(download_path.clone())
+                .with_base_url(&base_url)
+                .with_resume(true)
+                .build()
+                .await?;
+
+            std::fs::write(downloader.download_part_path(), &bundle[..bundle.len() / 2])?;
+            downloader.download().await?;
+
+            assert_eq!(
+                *requested.lock().unwrap(),
+                vec![Some((bundle.len() / 2).to_string())]
+            );
+            assert_eq!(std::fs::read(downloader.download_tarball_path())?, bundle);
+            assert!(downloader.tor_bin_path().is_file());
+
+            remove_dir_all(download_path)?;
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn downloads_all_targets() -> Result<()> {
+        let download_path = test_dir("downloads_all_targets")?;
+        let bundle_path = download_path.join("bundle.tar.gz");
+
+        write_bundle(&bundle_path)?;
+
+        let (base_url, requested) = serve_bundle(std::fs::read(&bundle_path)?, false).await?;
+        let downloader = DownloadOptions::default()
+            .with_download_path(download_path.clone())
+            .with_base_url(&base_url)
+            .with_parallelism(2)
+            .with_retries(0)
+            .build()
+            .await?;
+        let targets = [
+            Target::WindowsX8664,
+            Target::MacOSAarch64,
+            Target::AndroidX8664,
+        ];
+
+        // a directory in place of the tarball makes that target fail
+        create_dir_all(
+            DownloadOptions::default()
+                .with_download_path(download_path.clone())
+                .with_target(Target::MacOSAarch64)
+                .build()
+                .await?
+                .download_tarball_path(),
+        )?;
+
+        let results = downloader.download_all(&targets).await;
+
+        assert_eq!(
+            results
+                .iter()
+                .map(|(target, _)| target.clone())
+                .collect::<Vec<_>>(),
+            targets
+        );
+        assert!(results[1].1.is_err());
+
+        for index in [0, 2] {
+            let tarball_path = results[index].1.as_ref().unwrap();
+
+            assert!(tarball_path.is_file());
+            assert!(tarball_path
+                .to_string_lossy()
+                .contains(&targets[index].to_string()));
+        }
+
+        assert_eq!(requested.lock().unwrap().len(), 2);
+
+        remove_dir_all(download_path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reuses_verified_marker() -> Result<()> {
+        let download_path = test_dir("reuses_verified_marker")?;
+        let bundle_path = download_path.join("bundle.tar.gz");
+
+        write_bundle(&bundle_path)?;
+
+        let bundle = std::fs::read(&bundle_path)?;
+        let (base_url, requested) = serve_bundle(bundle.clone(), false).await?;
+        let downloader = DownloadOptions::default()
+            .with_download_path(download_path.clone())
+            .with_base_url(&base_url)
+            .build()
+            .await?;
+        let marker_path = downloader.verified_marker_path();
+
+        std::fs::copy(&bundle_path, downloader.download_tarball_path())?;
+        downloader.download().await?;
+
+        let digest = format!("{:x}", Sha256::digest(&bundle));
+
+        assert!(requested.lock().unwrap().is_empty());
+        assert!(std::fs::read_to_string(&marker_path)?.contains(&digest));
+
+        // a marker for the unchanged tarball is trusted over hashing it
+        let marker = std::fs::read_to_string(&marker_path)?.replace(&digest, &"0".repeat(64));
+
+        std::fs::write(&marker_path, marker)?;
+        downloader.download().await?;
+
+        assert_eq!(requested.lock().unwrap().len(), 1);
+        assert!(std::fs::read_to_string(&marker_path)?.contains(&digest));
+
+        remove_dir_all(download_path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn downloads_with_provided_client() -> Result<()> {
+        let download_path = test_dir("downloads_with_provided_client")?;
+        let bundle_path = download_path.join("bundle.tar.gz");
+
+        write_bundle(&bundle_path)?;
+
+        let (proxy_url, requested) = serve_bundle(std::fs::read(&bundle_path)?, false).await?;
+        // the mirror is only reachable through the proxy set on the client
+        let client = reqwest::Client::builder()
+            .proxy(reqwest::Proxy::http(&proxy_url)?)
+            .build()?;
+        let downloader = DownloadOptions::default()
+            .with_download_path(download_path.clone())
+            .with_base_url("http://archive.invalid")
+            .with_client(client)
+            .build()
+            .await?;
+
+        downloader.download().await?;
+
+        assert_eq!(requested.lock().unwrap().len(), 1);
+        assert!(downloader.tor_bin_path().is_file());
+
+        remove_dir_all(download_path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn builds_with_proxy() -> Result<()> {
+        let proxy = reqwest::Proxy::all("socks5h://127.0.0.1:9050")?;
+        let options = DownloadOptions::default().with_proxy(proxy);
+        let downloader = Downloader::new_with_options(options).await?;
+
+        assert_eq!(downloader.version(), DEFAULT_VERSION);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_default_version_selection() -> Result<()> {
         let options = DownloadOptions::default();
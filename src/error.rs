@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use thiserror::Error;
+
+pub type Result<T, E = TorError> = std::result::Result<T, E>;
+
+/// Errors returned by this crate.
+///
+/// Variants wrapping an [`anyhow::Error`] render its full context chain.
+#[derive(Debug, Error)]
+pub enum TorError {
+    /// Failed to download the Tor Expert Bundle or its metadata.
+    #[error("{0:#}")]
+    Download(anyhow::Error),
+    /// The downloaded Tor Expert Bundle digest doesn't match the published
+    /// one.
+    #[error("Checksum mismatch for Tor Expert Bundle: expected {expected}, got {actual}.")]
+    Checksum { expected: String, actual: String },
+    /// The filesystem holding the download path doesn't have room for the
+    /// Tor Expert Bundle and its unpacked contents.
+    #[error("Not enough disk space for Tor Expert Bundle: {required} bytes required, {available} bytes available.")]
+    InsufficientDiskSpace { required: u64, available: u64 },
+    /// Failed to unpack the Tor Expert Bundle tarball.
+    #[error("{0:#}")]
+    Unpack(anyhow::Error),
+    /// Failed to spawn the Tor process or read its output.
+    #[error("{0:#}")]
+    Spawn(anyhow::Error),
+    /// Tor could not bind one of its ports because another process, e.g. a
+    /// system Tor, already uses it. Holds the line Tor logged.
+    #[error("Tor port is already in use: {0}")]
+    PortInUse(String),
+    /// Tor was asked to run while the process it previously spawned, with
+    /// the held PID, is still running.
+    #[error("Tor is already running with PID {0}. Stop it before running it again.")]
+    AlreadyRunning(u32),
+    /// Tor did not bootstrap in time.
+    #[error("Tor did not bootstrap within {0:?}.")]
+    BootstrapTimeout(Duration),
+    /// Failed to communicate with the Tor Control Port.
+    #[error("{0:#}")]
+    Control(anyhow::Error),
+    #[error("{0:#}")]
+    Other(anyhow::Error),
+}
+
+impl From<anyhow::Error> for TorError {
+    fn from(err: anyhow::Error) -> Self {
+        TorError::Other(err)
+    }
+}
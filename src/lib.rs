@@ -1,12 +1,72 @@
+#[cfg(feature = "blocking")]
+pub mod blocking;
+mod config;
+mod control;
 mod downloader;
+mod error;
 mod tor;
+mod version;
 
-pub use downloader::{DownloadOptions, Downloader, Target};
-pub use tor::Tor;
+pub use config::{
+    LogLevel, OnionService, PluggableTransport, SocksIsolation, SocksPolicy, SocksPort, TorConfig,
+};
+pub use control::{
+    CircuitInfo, CircuitRelay, CircuitState, ControlReply, EphemeralOnionService, TorController,
+    TrafficStats,
+};
+pub use downloader::{
+    BundleInfo, ChecksumAlgorithm, DownloadOptions, DownloadPlan, DownloadProgress, Downloader,
+    Target,
+};
+pub use error::{Result, TorError};
+pub use tor::{
+    BootstrapProgress, BootstrapStatus, CheckResult, LogEvent, Tor, TorBuilder, TorListeners,
+    TorStatus,
+};
+pub use version::TorVersion;
 
 pub(crate) const DEFAULT_VERSION: &str = "14.0.4";
 pub(crate) const DOWNLOAD_DIRECTORY: &str = "RustTorProject";
 pub(crate) const DOWNLOAD_DIRECTORY_TOR: &str = "tor";
+pub(crate) const DOWNLOAD_DIRECTORY_PLUGGABLE_TRANSPORTS: &str = "pluggable_transports";
+pub(crate) const DOWNLOAD_DIRECTORY_DATA: &str = "data";
+
+/// Name of the Tor binary shipped in the Expert Bundle.
+#[cfg(target_os = "windows")]
+pub(crate) const TOR_BIN: &str = "tor.exe";
+
+/// Name of the Tor binary shipped in the Expert Bundle.
+#[cfg(not(target_os = "windows"))]
+pub(crate) const TOR_BIN: &str = "tor";
+pub(crate) const DEFAULT_SOCKS_PORT: u16 = 9050;
+
+/// Creates a [`reqwest::ClientBuilder`] using the TLS backend selected by
+/// the `rustls` or `native-tls` features. `rustls` wins when both are on.
+pub(crate) fn client_builder() -> reqwest::ClientBuilder {
+    let builder = reqwest::Client::builder();
+
+    #[cfg(feature = "rustls")]
+    let builder = builder.use_rustls_tls();
+
+    #[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+    let builder = builder.use_native_tls();
+
+    builder
+}
+
+/// Blocking counterpart of [`client_builder`].
+#[cfg(feature = "blocking")]
+pub(crate) fn blocking_client_builder() -> reqwest::blocking::ClientBuilder {
+    let builder = reqwest::blocking::Client::builder();
+
+    #[cfg(feature = "rustls")]
+    let builder = builder.use_rustls_tls();
+
+    #[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+    let builder = builder.use_native_tls();
+
+    builder
+}
 
 #[derive(Debug, Clone)]
 pub enum VersionSelection {
@@ -29,13 +89,12 @@ mod tests {
     use crate::Tor;
 
     const TOR_CHECK_WEB: &str = "https://check.torproject.org/";
-    const TOR_SOCK5_LOCAL: &str = "socks5://127.0.0.1:9050";
 
     #[tokio::test]
     async fn check_tor_conn() -> Result<()> {
         let mut tor = Tor::setup().await?;
         tor.run().await?;
-        let proxy = Proxy::all(TOR_SOCK5_LOCAL)?;
+        let proxy = Proxy::all(tor.socks_proxy_url()?)?;
         let client = Client::builder().proxy(proxy).build()?;
         let res_html = client.get(TOR_CHECK_WEB).send().await?.text().await?;
         let contains = res_html.contains("Congratulations. This browser is configured to use Tor.");
@@ -0,0 +1,165 @@
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+use anyhow::Context;
+
+/// Version of the Tor Expert Bundle as published on the Tor archive, e.g.
+/// `14.0.4` or `14.5a1`.
+///
+/// The archive doesn't follow semver: `14.0` lacks a patch component and
+/// alphas carry their number right after the minor version. Versions are
+/// ordered numerically, including the alpha number, with any pre-release
+/// preceding the stable release of the same version.
+#[derive(Clone, Debug)]
+pub struct TorVersion {
+    raw: String,
+    numbers: [u64; 3],
+    pre: Option<(String, u64)>,
+}
+
+impl TorVersion {
+    /// The version as published on the Tor archive.
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.raw
+    }
+
+    /// Whether this is a stable release, as opposed to an alpha, beta or
+    /// release candidate.
+    #[inline]
+    pub fn is_stable(&self) -> bool {
+        self.pre.is_none()
+    }
+
+    /// Ordering key, leaving out how the version was written.
+    fn key(&self) -> ([u64; 3], Option<(&str, u64)>) {
+        (
+            self.numbers,
+            self.pre
+                .as_ref()
+                .map(|(tag, number)| (tag.as_str(), *number)),
+        )
+    }
+}
+
+impl FromStr for TorVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let parse = || {
+            let split_at = s
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(s.len());
+            let (numbers, pre) = s.split_at(split_at);
+            let mut parts = numbers.split('.').map(|n| n.parse::<u64>().ok());
+            let major = parts.next()??;
+            let minor = parts.next().unwrap_or(Some(0))?;
+            let patch = parts.next().unwrap_or(Some(0))?;
+
+            if parts.next().is_some() {
+                return None;
+            }
+
+            let pre = match pre {
+                "" => None,
+                pre => {
+                    let pre = pre.strip_prefix('-').unwrap_or(pre);
+                    let split_at = pre.find(|c: char| c.is_ascii_digit())?;
+                    let (tag, number) = pre.split_at(split_at);
+
+                    if !tag.chars().all(|c| c.is_ascii_alphabetic()) {
+                        return None;
+                    }
+
+                    Some((tag.to_ascii_lowercase(), number.parse().ok()?))
+                }
+            };
+
+            Some(TorVersion {
+                raw: s.to_string(),
+                numbers: [major, minor, patch],
+                pre,
+            })
+        };
+
+        parse().with_context(|| format!("Invalid Tor Expert Bundle version: {s}."))
+    }
+}
+
+impl Display for TorVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+impl PartialEq for TorVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for TorVersion {}
+
+impl Hash for TorVersion {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl PartialOrd for TorVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TorVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (numbers, pre) = self.key();
+        let (other_numbers, other_pre) = other.key();
+
+        numbers
+            .cmp(&other_numbers)
+            .then_with(|| match (pre, other_pre) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(pre), Some(other_pre)) => pre.cmp(&other_pre),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TorVersion;
+
+    fn version(s: &str) -> TorVersion {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_archive_versions() {
+        assert!(version("14.0.4").is_stable());
+        assert!(!version("14.5a1").is_stable());
+        assert_eq!(version("14.0"), version("14.0.0"));
+        assert_eq!(version("14.0").to_string(), "14.0");
+
+        for invalid in ["icons", "", "14.0.4.1", "14.5a", "14.5-"] {
+            assert!(invalid.parse::<TorVersion>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn orders_versions() {
+        let mut versions =
+            ["14.5a10", "14.0.10", "14.5", "14.5a2", "13.5.9", "14.0.4"].map(version);
+
+        versions.sort();
+
+        assert_eq!(
+            versions.map(|v| v.to_string()),
+            ["13.5.9", "14.0.4", "14.0.10", "14.5a2", "14.5a10", "14.5"]
+        );
+    }
+}